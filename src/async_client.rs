@@ -0,0 +1,340 @@
+//! A non-blocking variant of [`crate::client::Client`] for integration with an external reactor.
+//!
+//! This shares the SASL handshake ([`crate::conn::Connection::new`]) and message framing
+//! ([`crate::message`]) with the synchronous client; only the I/O driving loop differs. The connection's
+//! socket is put into non-blocking mode, and [`AsyncClient::method_call`] / [`AsyncClient::recv_signal`]
+//! return futures that complete once a reply or signal has been read. This crate does not bundle a
+//! reactor: the caller is responsible for calling [`AsyncClient::poll_read`] whenever [`AsyncClient::as_raw_fd`]
+//! becomes readable, and [`AsyncClient::poll_write`] whenever it becomes writable and bytes are still queued.
+//!
+//! [`AsyncClient`] is a cheaply-[`Clone`]able handle onto shared connection state, so it (or the futures
+//! it returns) can be held by the reactor loop and by in-flight calls at the same time.
+
+use std::{
+	cell::RefCell,
+	collections::{HashMap, HashSet, VecDeque},
+	future::Future,
+	os::unix::io::RawFd,
+	pin::Pin,
+	rc::Rc,
+	task::{Context, Poll, Waker},
+};
+
+use crate::{
+	client::{message_into_signal, Signal},
+	conn::Connection,
+	message::{Message, MessageType, FLAG_NO_REPLY_EXPECTED},
+	types::{ObjectPath, Variant},
+};
+
+/// An async-friendly client connected to a message bus. See the [module-level docs](self) for how to drive it.
+#[derive(Clone)]
+pub struct AsyncClient {
+	inner: Rc<RefCell<Inner>>,
+}
+
+struct Inner {
+	connection: Connection,
+	unique_name: String,
+	next_serial: u32,
+	/// Serials of method calls whose [`MethodCallFuture`] is still alive. A reply for a serial not in
+	/// here belongs to a call that was already dropped (eg cancelled by a `select!`/timeout), so
+	/// `dispatch` discards it instead of stashing it in `replies` forever.
+	live_calls: HashSet<u32>,
+	replies: HashMap<u32, Result<Option<Variant>, AsyncClientError>>,
+	reply_wakers: HashMap<u32, Waker>,
+	pending_signals: VecDeque<Signal>,
+	/// Wakers of every currently-pending [`SignalFuture`]. A plain `Option<Waker>` would only remember
+	/// the most recently polled waiter, silently dropping any others waiting concurrently.
+	signal_wakers: Vec<Waker>,
+}
+
+impl AsyncClient {
+	/// Wraps a [`Connection`] in an `AsyncClient`, registering it with the bus via `org.freedesktop.DBus.Hello`
+	/// and then putting its socket into non-blocking mode.
+	pub fn new(mut connection: Connection) -> std::io::Result<Self> {
+		let unique_name = hello(&mut connection)?;
+
+		connection.set_nonblocking(true)?;
+
+		Ok(AsyncClient {
+			inner: Rc::new(RefCell::new(Inner {
+				connection,
+				unique_name,
+				next_serial: 2,
+				live_calls: HashSet::new(),
+				replies: HashMap::new(),
+				reply_wakers: HashMap::new(),
+				pending_signals: VecDeque::new(),
+				signal_wakers: Vec::new(),
+			})),
+		})
+	}
+
+	/// The bus's assigned unique name for this connection, eg `:1.42`.
+	pub fn unique_name(&self) -> String {
+		self.inner.borrow().unique_name.clone()
+	}
+
+	/// The connection's underlying file descriptor, to register with the caller's reactor.
+	pub fn as_raw_fd(&self) -> RawFd {
+		self.inner.borrow().connection.as_raw_fd()
+	}
+
+	/// Reads and dispatches every complete message currently available without blocking.
+	///
+	/// Call this whenever [`AsyncClient::as_raw_fd`] becomes readable.
+	pub fn poll_read(&self) -> std::io::Result<()> {
+		let mut inner = self.inner.borrow_mut();
+
+		loop {
+			let available_fds = inner.connection.peek_fds();
+			while let Some((message, consumed, num_fds)) =
+				Message::deserialize(inner.connection.read_buf(), &available_fds).map_err(std::io::Error::other)?
+			{
+				inner.connection.consume(consumed);
+				inner.connection.take_fds(num_fds);
+				inner.dispatch(message);
+			}
+
+			match inner.connection.recv() {
+				Ok(()) => {},
+				Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+				Err(err) => return Err(err),
+			}
+		}
+	}
+
+	/// Attempts to send any bytes still queued by a previous [`AsyncClient::method_call`] or
+	/// [`AsyncClient::emit_signal`] call. Returns `true` once nothing remains queued.
+	///
+	/// Call this whenever [`AsyncClient::as_raw_fd`] becomes writable.
+	pub fn poll_write(&self) -> std::io::Result<bool> {
+		match self.inner.borrow_mut().connection.flush() {
+			Ok(()) => Ok(true),
+			Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+			Err(err) => Err(err),
+		}
+	}
+
+	/// Queues a method call and returns a future that resolves to its reply once one is read by
+	/// [`AsyncClient::poll_read`]. The call is not actually sent until [`AsyncClient::poll_write`] succeeds.
+	pub fn method_call(
+		&self,
+		destination: String,
+		path: ObjectPath,
+		interface: String,
+		member: String,
+		body: Option<&Variant>,
+	) -> MethodCallFuture {
+		let mut inner = self.inner.borrow_mut();
+
+		let serial = inner.next_serial;
+		inner.next_serial += 1;
+
+		let message = Message {
+			r#type: MessageType::MethodCall,
+			flags: 0,
+			serial,
+			path: Some(path),
+			interface: Some(interface),
+			member: Some(member),
+			error_name: None,
+			reply_serial: None,
+			destination: Some(destination),
+			sender: None,
+			body: body.cloned(),
+		};
+
+		inner.live_calls.insert(serial);
+
+		let mut fds = vec![];
+		message.serialize(inner.connection.write_buf(), &mut fds);
+		inner.connection.queue_fds(&fds);
+
+		drop(inner);
+
+		MethodCallFuture { inner: Rc::clone(&self.inner), serial }
+	}
+
+	/// Queues a signal with no expectation of a reply. The signal is not actually sent until
+	/// [`AsyncClient::poll_write`] succeeds.
+	pub fn emit_signal(&self, path: ObjectPath, interface: String, member: String, body: Option<&Variant>) {
+		let mut inner = self.inner.borrow_mut();
+
+		let serial = inner.next_serial;
+		inner.next_serial += 1;
+
+		let message = Message {
+			r#type: MessageType::Signal,
+			flags: FLAG_NO_REPLY_EXPECTED,
+			serial,
+			path: Some(path),
+			interface: Some(interface),
+			member: Some(member),
+			error_name: None,
+			reply_serial: None,
+			destination: None,
+			sender: None,
+			body: body.cloned(),
+		};
+
+		let mut fds = vec![];
+		message.serialize(inner.connection.write_buf(), &mut fds);
+		inner.connection.queue_fds(&fds);
+	}
+
+	/// Returns a future that resolves to the next signal received.
+	pub fn recv_signal(&self) -> SignalFuture {
+		SignalFuture { inner: Rc::clone(&self.inner) }
+	}
+}
+
+impl Inner {
+	fn dispatch(&mut self, message: Message) {
+		match message.r#type {
+			MessageType::MethodReturn | MessageType::Error if message.reply_serial.is_some() => {
+				let serial = message.reply_serial.unwrap();
+
+				let result = match message.r#type {
+					MessageType::MethodReturn => Ok(message.body),
+					_ => Err(AsyncClientError::MethodCallFailed { error_name: message.error_name.unwrap_or_default(), body: message.body }),
+				};
+
+				if self.live_calls.contains(&serial) {
+					self.replies.insert(serial, result);
+					if let Some(waker) = self.reply_wakers.remove(&serial) {
+						waker.wake();
+					}
+				}
+				// Else: the `MethodCallFuture` waiting on this serial was already dropped (eg cancelled);
+				// there's nothing left to deliver the reply to.
+			},
+
+			MessageType::Signal => {
+				self.pending_signals.push_back(message_into_signal(message));
+				for waker in self.signal_wakers.drain(..) {
+					waker.wake();
+				}
+			},
+
+			// A method call directed at us, or a reply that carries no reply serial.
+			_ => {},
+		}
+	}
+}
+
+/// Performs the `org.freedesktop.DBus.Hello` round trip synchronously, before the connection is put
+/// into non-blocking mode. Shares [`crate::message::Message`] and [`Connection`] with [`crate::client::Client::new`].
+fn hello(connection: &mut Connection) -> std::io::Result<String> {
+	let serial = 1;
+
+	let message = Message {
+		r#type: MessageType::MethodCall,
+		flags: 0,
+		serial,
+		path: Some(ObjectPath("/org/freedesktop/DBus".to_owned())),
+		interface: Some("org.freedesktop.DBus".to_owned()),
+		member: Some("Hello".to_owned()),
+		error_name: None,
+		reply_serial: None,
+		destination: Some("org.freedesktop.DBus".to_owned()),
+		sender: None,
+		body: None,
+	};
+
+	let mut fds = vec![];
+	message.serialize(connection.write_buf(), &mut fds);
+	connection.queue_fds(&fds);
+	connection.flush()?;
+
+	loop {
+		let available_fds = connection.peek_fds();
+		if let Some((reply, consumed, num_fds)) =
+			Message::deserialize(connection.read_buf(), &available_fds).map_err(std::io::Error::other)?
+		{
+			connection.consume(consumed);
+			connection.take_fds(num_fds);
+
+			if reply.r#type == MessageType::MethodReturn && reply.reply_serial == Some(serial) {
+				return reply.body
+					.ok_or_else(|| std::io::Error::other("bus sent an empty Hello reply"))?
+					.into_string()
+					.map_err(|_| std::io::Error::other("bus sent a non-string Hello reply"));
+			}
+		}
+
+		connection.recv()?;
+	}
+}
+
+/// A future returned by [`AsyncClient::method_call`].
+pub struct MethodCallFuture {
+	inner: Rc<RefCell<Inner>>,
+	serial: u32,
+}
+
+impl Future for MethodCallFuture {
+	type Output = Result<Option<Variant>, AsyncClientError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut inner = self.inner.borrow_mut();
+
+		if let Some(result) = inner.replies.remove(&self.serial) {
+			return Poll::Ready(result);
+		}
+
+		inner.reply_wakers.insert(self.serial, cx.waker().clone());
+		Poll::Pending
+	}
+}
+
+impl Drop for MethodCallFuture {
+	/// Cleans up this call's serial if the future is dropped before its reply arrives, eg because the
+	/// caller cancelled it -- otherwise the reply (and its waker) would sit in [`Inner`] forever.
+	fn drop(&mut self) {
+		let mut inner = self.inner.borrow_mut();
+		inner.live_calls.remove(&self.serial);
+		inner.replies.remove(&self.serial);
+		inner.reply_wakers.remove(&self.serial);
+	}
+}
+
+/// A future returned by [`AsyncClient::recv_signal`].
+pub struct SignalFuture {
+	inner: Rc<RefCell<Inner>>,
+}
+
+impl Future for SignalFuture {
+	type Output = Signal;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut inner = self.inner.borrow_mut();
+
+		if let Some(signal) = inner.pending_signals.pop_front() {
+			return Poll::Ready(signal);
+		}
+
+		inner.signal_wakers.push(cx.waker().clone());
+		Poll::Pending
+	}
+}
+
+/// An error using an [`AsyncClient`].
+#[derive(Debug)]
+pub enum AsyncClientError {
+	MethodCallFailed {
+		error_name: String,
+		body: Option<Variant>,
+	},
+}
+
+impl std::fmt::Display for AsyncClientError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			AsyncClientError::MethodCallFailed { error_name, body: _ } => write!(f, "method call failed with {}", error_name),
+		}
+	}
+}
+
+impl std::error::Error for AsyncClientError {}
@@ -0,0 +1,305 @@
+//! A client for sending method calls to a message bus and receiving signals from it.
+
+use std::os::unix::io::RawFd;
+
+use crate::{
+	message::{Message, MessageType, FLAG_NO_REPLY_EXPECTED},
+	types::{ObjectPath, Variant},
+};
+
+/// A client connected to a message bus.
+pub struct Client {
+	connection: crate::conn::Connection,
+	unique_name: String,
+	next_serial: u32,
+	pending_signals: std::collections::VecDeque<Signal>,
+	pending_method_calls: std::collections::VecDeque<Message>,
+}
+
+/// A signal received from the message bus, eg `org.freedesktop.DBus.Properties.PropertiesChanged`.
+#[derive(Clone, Debug)]
+pub struct Signal {
+	pub sender: String,
+	pub path: ObjectPath,
+	pub interface: String,
+	pub member: String,
+	pub body: Option<Variant>,
+}
+
+/// A rule describing which signals to receive, for use with [`Client::add_match`] and [`Client::remove_match`].
+///
+/// Corresponds to the match rule syntax accepted by `org.freedesktop.DBus.AddMatch`. Only fields that are `Some`
+/// are included in the rule; a rule with every field `None` matches every signal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MatchRule<'a> {
+	pub sender: Option<&'a str>,
+	pub interface: Option<&'a str>,
+	pub member: Option<&'a str>,
+	pub path: Option<&'a str>,
+}
+
+impl MatchRule<'_> {
+	fn to_rule_string(self) -> String {
+		let mut parts = vec!["type='signal'".to_owned()];
+
+		if let Some(sender) = self.sender {
+			parts.push(format!("sender='{}'", sender));
+		}
+		if let Some(interface) = self.interface {
+			parts.push(format!("interface='{}'", interface));
+		}
+		if let Some(member) = self.member {
+			parts.push(format!("member='{}'", member));
+		}
+		if let Some(path) = self.path {
+			parts.push(format!("path='{}'", path));
+		}
+
+		parts.join(",")
+	}
+}
+
+impl Client {
+	/// Wraps a [`crate::conn::Connection`] in a `Client`, registering it with the bus via `org.freedesktop.DBus.Hello`.
+	pub fn new(connection: crate::conn::Connection) -> Result<Self, ClientError> {
+		let mut client = Client {
+			connection,
+			unique_name: String::new(),
+			next_serial: 1,
+			pending_signals: Default::default(),
+			pending_method_calls: Default::default(),
+		};
+
+		let unique_name =
+			client.method_call(
+				"org.freedesktop.DBus".to_owned(),
+				ObjectPath("/org/freedesktop/DBus".to_owned()),
+				"org.freedesktop.DBus".to_owned(),
+				"Hello".to_owned(),
+				None,
+			)?
+			.ok_or(ClientError::UnexpectedResponse)?
+			.into_string()
+			.map_err(|_| ClientError::UnexpectedResponse)?;
+
+		client.unique_name = unique_name;
+
+		Ok(client)
+	}
+
+	/// The bus's assigned unique name for this connection, eg `:1.42`.
+	pub fn unique_name(&self) -> &str {
+		&self.unique_name
+	}
+
+	/// Calls a method and waits for its reply, if any.
+	pub fn method_call(
+		&mut self,
+		destination: String,
+		path: ObjectPath,
+		interface: String,
+		member: String,
+		body: Option<&Variant>,
+	) -> Result<Option<Variant>, ClientError> {
+		let serial = self.send_serial(&Message {
+			r#type: MessageType::MethodCall,
+			flags: 0,
+			serial: 0,
+			path: Some(path),
+			interface: Some(interface),
+			member: Some(member),
+			error_name: None,
+			reply_serial: None,
+			destination: Some(destination),
+			sender: None,
+			body: body.cloned(),
+		})?;
+
+		loop {
+			let message = self.recv_message_raw()?;
+
+			match message.r#type {
+				MessageType::MethodReturn if message.reply_serial == Some(serial) => return Ok(message.body),
+
+				MessageType::Error if message.reply_serial == Some(serial) =>
+					return Err(ClientError::MethodCallFailed {
+						error_name: message.error_name.unwrap_or_default(),
+						body: message.body,
+					}),
+
+				MessageType::Signal => self.pending_signals.push_back(message_into_signal(message)),
+
+				MessageType::MethodCall => self.pending_method_calls.push_back(message),
+
+				// A reply to some other in-flight call. This client does not support concurrent in-flight
+				// calls, so it is dropped.
+				_ => {},
+			}
+		}
+	}
+
+	/// Emits a signal with no expectation of a reply. This is the server-side counterpart to [`Client::recv_signal`].
+	pub fn emit_signal(
+		&mut self,
+		path: ObjectPath,
+		interface: String,
+		member: String,
+		body: Option<&Variant>,
+	) -> Result<(), ClientError> {
+		self.send_serial(&Message {
+			r#type: MessageType::Signal,
+			flags: FLAG_NO_REPLY_EXPECTED,
+			serial: 0,
+			path: Some(path),
+			interface: Some(interface),
+			member: Some(member),
+			error_name: None,
+			reply_serial: None,
+			destination: None,
+			sender: None,
+			body: body.cloned(),
+		})?;
+
+		Ok(())
+	}
+
+	/// Installs a match rule with the bus so that matching signals are delivered to [`Client::recv_signal`].
+	pub fn add_match(&mut self, rule: MatchRule<'_>) -> Result<(), ClientError> {
+		self.method_call(
+			"org.freedesktop.DBus".to_owned(),
+			ObjectPath("/org/freedesktop/DBus".to_owned()),
+			"org.freedesktop.DBus".to_owned(),
+			"AddMatch".to_owned(),
+			Some(&Variant::Tuple { elements: vec![Variant::String(rule.to_rule_string())] }),
+		)?;
+
+		Ok(())
+	}
+
+	/// Removes a previously-installed match rule.
+	pub fn remove_match(&mut self, rule: MatchRule<'_>) -> Result<(), ClientError> {
+		self.method_call(
+			"org.freedesktop.DBus".to_owned(),
+			ObjectPath("/org/freedesktop/DBus".to_owned()),
+			"org.freedesktop.DBus".to_owned(),
+			"RemoveMatch".to_owned(),
+			Some(&Variant::Tuple { elements: vec![Variant::String(rule.to_rule_string())] }),
+		)?;
+
+		Ok(())
+	}
+
+	/// Blocks until a signal matching a previously-installed [`MatchRule`] is received.
+	///
+	/// Method call replies received while waiting are discarded, so this should not be called
+	/// while a [`Client::method_call`] is conceptually still in flight from another thread.
+	pub fn recv_signal(&mut self) -> Result<Signal, ClientError> {
+		if let Some(signal) = self.pending_signals.pop_front() {
+			return Ok(signal);
+		}
+
+		loop {
+			let message = self.recv_message_raw()?;
+			match message.r#type {
+				MessageType::Signal => return Ok(message_into_signal(message)),
+				MessageType::MethodCall => self.pending_method_calls.push_back(message),
+				_ => {},
+			}
+		}
+	}
+
+	/// Blocks until an incoming method call directed at this client is received.
+	///
+	/// Signals received while waiting are queued for [`Client::recv_signal`] as usual.
+	pub(crate) fn recv_method_call(&mut self) -> Result<Message, ClientError> {
+		if let Some(message) = self.pending_method_calls.pop_front() {
+			return Ok(message);
+		}
+
+		loop {
+			let message = self.recv_message_raw()?;
+			match message.r#type {
+				MessageType::MethodCall => return Ok(message),
+				MessageType::Signal => self.pending_signals.push_back(message_into_signal(message)),
+				_ => {},
+			}
+		}
+	}
+
+	pub(crate) fn send_serial(&mut self, message: &Message) -> Result<u32, ClientError> {
+		let serial = self.next_serial;
+		self.next_serial += 1;
+
+		let mut message = message.clone();
+		message.serial = serial;
+
+		let mut fds: Vec<RawFd> = vec![];
+		message.serialize(self.connection.write_buf(), &mut fds);
+		self.connection.queue_fds(&fds);
+		self.connection.flush().map_err(ClientError::Io)?;
+
+		Ok(serial)
+	}
+
+	fn recv_message_raw(&mut self) -> Result<Message, ClientError> {
+		loop {
+			let available_fds = self.connection.peek_fds();
+			if let Some((message, consumed, num_fds)) =
+				Message::deserialize(self.connection.read_buf(), &available_fds).map_err(ClientError::Parse)?
+			{
+				self.connection.consume(consumed);
+				self.connection.take_fds(num_fds);
+				return Ok(message);
+			}
+
+			self.connection.recv().map_err(ClientError::Io)?;
+		}
+	}
+}
+
+pub(crate) fn message_into_signal(message: Message) -> Signal {
+	Signal {
+		sender: message.sender.unwrap_or_default(),
+		path: message.path.unwrap_or(ObjectPath(String::new())),
+		interface: message.interface.unwrap_or_default(),
+		member: message.member.unwrap_or_default(),
+		body: message.body,
+	}
+}
+
+/// An error using a [`Client`].
+#[derive(Debug)]
+pub enum ClientError {
+	Io(std::io::Error),
+
+	Parse(crate::message::MessageParseError),
+
+	MethodCallFailed {
+		error_name: String,
+		body: Option<Variant>,
+	},
+
+	UnexpectedResponse,
+}
+
+impl std::fmt::Display for ClientError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ClientError::Io(_) => f.write_str("I/O error communicating with bus"),
+			ClientError::Parse(_) => f.write_str("could not parse message from bus"),
+			ClientError::MethodCallFailed { error_name, body: _ } => write!(f, "method call failed with {}", error_name),
+			ClientError::UnexpectedResponse => f.write_str("bus sent an unexpected response"),
+		}
+	}
+}
+
+impl std::error::Error for ClientError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ClientError::Io(err) => Some(err),
+			ClientError::Parse(err) => Some(err),
+			ClientError::MethodCallFailed { .. } => None,
+			ClientError::UnexpectedResponse => None,
+		}
+	}
+}
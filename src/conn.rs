@@ -1,19 +1,26 @@
 /// A connection to a message bus.
 pub struct Connection {
-	reader: std::io::BufReader<std::os::unix::net::UnixStream>,
+	reader: std::io::BufReader<Stream>,
 	read_buf: Vec<u8>,
 	read_end: usize,
-	writer: std::os::unix::net::UnixStream,
+	read_fds: std::collections::VecDeque<std::os::unix::io::RawFd>,
+	writer: Stream,
 	write_buf: Vec<u8>,
+	write_fds: Vec<std::os::unix::io::RawFd>,
 	server_guid: Vec<u8>,
+	unix_fd_negotiated: bool,
 }
 
 /// The path of a message bus.
 #[derive(Clone, Copy, Debug)]
 pub enum BusPath<'a> {
-	/// The session bus. Its path will be determined from the `DBUS_SESSION_BUS_ADDRESS` environment variable.
+	/// The session bus. Its address will be determined from the `DBUS_SESSION_BUS_ADDRESS` environment variable.
 	Session,
 
+	/// The system bus. Its address will be determined from the `DBUS_SYSTEM_BUS_ADDRESS` environment variable,
+	/// falling back to `unix:path=/var/run/dbus/system_bus_socket` if that variable is not set.
+	System,
+
 	/// A unix domain socket file at the specified filesystem path.
 	UnixSocketFile(&'a std::path::Path),
 }
@@ -28,6 +35,11 @@ pub enum SaslAuthType<'a> {
 
 	/// The specified string will be used.
 	Other(&'a str),
+
+	/// SASL DBUS_COOKIE_SHA1 authentication will be used, with the given username.
+	///
+	/// The cookie is looked up in `~/.dbus-keyrings/<cookie context>`, where the cookie context is sent by the server.
+	CookieSha1(&'a str),
 }
 
 impl Connection {
@@ -36,47 +48,35 @@ impl Connection {
 		bus_path: BusPath<'_>,
 		sasl_auth_type: SaslAuthType<'_>,
 	) -> Result<Self, ConnectError> {
-		use std::io::{BufRead, Write};
+		use std::io::Write;
 
 		let stream = match bus_path {
 			BusPath::Session => {
-				let session_bus_address = std::env::var_os("DBUS_SESSION_BUS_ADDRESS").ok_or_else(|| ConnectError::SessionBusEnvVar(None))?;
-				let bus_path: &std::ffi::OsStr = {
-					let session_bus_address_bytes = std::os::unix::ffi::OsStrExt::as_bytes(&*session_bus_address);
-					if session_bus_address_bytes.starts_with(b"unix:path=") {
-						std::os::unix::ffi::OsStrExt::from_bytes(&session_bus_address_bytes["unix:path=".len()..])
-					}
-					else {
-						return Err(ConnectError::SessionBusEnvVar(Some(session_bus_address)));
-					}
-				};
-				let bus_path = std::path::Path::new(bus_path);
-				let stream =
-					std::os::unix::net::UnixStream::connect(bus_path)
-					.map_err(|err| ConnectError::Connect { bus_path: bus_path.to_owned(), err, })?;
-				stream
+				let session_bus_address =
+					std::env::var_os("DBUS_SESSION_BUS_ADDRESS")
+					.ok_or(ConnectError::SessionBusEnvVar)?;
+				let session_bus_address =
+					session_bus_address.to_str()
+					.ok_or_else(|| ConnectError::MalformedAddress(session_bus_address.to_string_lossy().into_owned()))?
+					.to_owned();
+				connect_address(&session_bus_address)?
+			},
+
+			BusPath::System => {
+				let system_bus_address =
+					std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+					.unwrap_or_else(|_| "unix:path=/var/run/dbus/system_bus_socket".to_owned());
+				connect_address(&system_bus_address)?
 			},
 
 			BusPath::UnixSocketFile(bus_path) => {
 				let stream =
 					std::os::unix::net::UnixStream::connect(bus_path)
 					.map_err(|err| ConnectError::Connect { bus_path: bus_path.to_owned(), err, })?;
-				stream
+				Stream::Unix(stream)
 			},
 		};
 
-		let sasl_auth_id: std::borrow::Cow<'_, str> = match sasl_auth_type {
-			SaslAuthType::Uid =>
-				(unsafe { libc::getuid() })
-				.to_string()
-				.chars()
-				.map(|c| format!("{:2x}", c as u32))
-				.collect::<String>()
-				.into(),
-
-			SaslAuthType::Other(sasl_auth_id) => sasl_auth_id.into(),
-		};
-
 		let reader = stream.try_clone().map_err(ConnectError::Authenticate)?;
 		let mut reader = std::io::BufReader::new(reader);
 		let mut read_buf = vec![];
@@ -84,22 +84,40 @@ impl Connection {
 		let mut writer = stream;
 		let write_buf = vec![];
 
-		write!(writer, "\0AUTH EXTERNAL {}\r\n", sasl_auth_id).map_err(ConnectError::Authenticate)?;
-		writer.flush().map_err(ConnectError::Authenticate)?;
+		let server_guid = match sasl_auth_type {
+			SaslAuthType::Uid | SaslAuthType::Other(_) => {
+				let sasl_auth_id: std::borrow::Cow<'_, str> = match sasl_auth_type {
+					SaslAuthType::Uid =>
+						(unsafe { libc::getuid() })
+						.to_string()
+						.chars()
+						.map(|c| format!("{:2x}", c as u32))
+						.collect::<String>()
+						.into(),
 
-		let _ = reader.read_until(b'\n', &mut read_buf).map_err(ConnectError::Authenticate)?;
-		if read_buf.iter().rev().nth(1).copied() != Some(b'\r') {
-			return Err(ConnectError::Authenticate(std::io::Error::new(std::io::ErrorKind::Other, "malformed response")));
-		}
+					SaslAuthType::Other(sasl_auth_id) => sasl_auth_id.into(),
+
+					SaslAuthType::CookieSha1(_) => unreachable!(),
+				};
+
+				write!(writer, "\0AUTH EXTERNAL {}\r\n", sasl_auth_id).map_err(ConnectError::Authenticate)?;
+				writer.flush().map_err(ConnectError::Authenticate)?;
 
-		let server_guid =
-			if read_buf.starts_with(b"OK ") {
-				&read_buf[b"OK ".len()..(b"OK ".len() + 32)]
+				read_sasl_ok(&mut reader, &mut read_buf)?
+			},
+
+			SaslAuthType::CookieSha1(username) =>
+				sasl_auth_cookie_sha1(&mut reader, &mut writer, &mut read_buf, username)?,
+		};
+
+		// Only unix transports can carry file descriptors, so only bother negotiating over one.
+		let unix_fd_negotiated =
+			if matches!(writer, Stream::Unix(_)) {
+				negotiate_unix_fd(&mut reader, &mut writer, &mut read_buf)?
 			}
 			else {
-				return Err(ConnectError::Authenticate(std::io::Error::new(std::io::ErrorKind::Other, "malformed response")));
+				false
 			};
-		let server_guid = server_guid.to_owned();
 
 		read_buf.clear();
 		read_buf.resize(1, 0);
@@ -111,9 +129,12 @@ impl Connection {
 			reader,
 			read_buf,
 			read_end: 0,
+			read_fds: Default::default(),
 			writer,
 			write_buf,
+			write_fds: vec![],
 			server_guid,
+			unix_fd_negotiated,
 		})
 	}
 
@@ -122,17 +143,52 @@ impl Connection {
 		&self.server_guid
 	}
 
+	/// Whether the server agreed to `NEGOTIATE_UNIX_FD`, ie whether file descriptors can be passed over this connection.
+	pub fn unix_fd_negotiated(&self) -> bool {
+		self.unix_fd_negotiated
+	}
+
+	/// Puts the connection's socket into non-blocking mode, for use with [`crate::async_client::AsyncClient`].
+	///
+	/// Once in non-blocking mode, [`Connection::recv`] and [`Connection::flush`] can fail with
+	/// [`std::io::ErrorKind::WouldBlock`] instead of waiting for the socket to become ready.
+	pub fn set_nonblocking(&mut self, nonblocking: bool) -> std::io::Result<()> {
+		self.reader.get_ref().set_nonblocking(nonblocking)?;
+		self.writer.set_nonblocking(nonblocking)
+	}
+
+	/// The connection's underlying file descriptor, for registering with a reactor after [`Connection::set_nonblocking`].
+	pub fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+		use std::os::unix::io::AsRawFd;
+		self.writer.as_raw_fd()
+	}
+
 	pub(crate) fn write_buf(&mut self) -> &mut Vec<u8> {
 		&mut self.write_buf
 	}
 
-	pub(crate) fn flush(&mut self) -> std::io::Result<()> {
-		use std::io::Write;
+	/// Queues file descriptors to be sent along with the bytes currently in [`Connection::write_buf`]
+	/// the next time [`Connection::flush`] is called.
+	pub(crate) fn queue_fds(&mut self, fds: &[std::os::unix::io::RawFd]) {
+		self.write_fds.extend_from_slice(fds);
+	}
 
-		self.writer.write_all(&self.write_buf)?;
-		self.write_buf.clear();
+	pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+		let mut data = &self.write_buf[..];
+		let mut fds = &self.write_fds[..];
+
+		let result = self.writer.write_all_with_fds(&mut data, &mut fds);
+
+		// Even on error (eg `WouldBlock` after a partial send on a non-blocking connection),
+		// `write_all_with_fds` advances `data`/`fds` past whatever was actually sent, so trim that
+		// prefix here too -- otherwise the next `flush` would resend bytes the peer already got.
+		let sent = self.write_buf.len() - data.len();
+		if sent > 0 {
+			self.write_buf.drain(..sent);
+			self.write_fds.clear();
+		}
 
-		self.writer.flush()?;
+		result?;
 
 		Ok(())
 	}
@@ -141,19 +197,28 @@ impl Connection {
 		&self.read_buf[..self.read_end]
 	}
 
-	pub(crate) fn recv(&mut self) -> std::io::Result<()> {
-		use std::io::Read;
+	/// The file descriptors that have been received but not yet claimed by [`Connection::take_fds`], in the order they arrived.
+	pub(crate) fn peek_fds(&self) -> Vec<std::os::unix::io::RawFd> {
+		self.read_fds.iter().copied().collect()
+	}
 
+	/// Removes and returns the oldest `count` received file descriptors.
+	pub(crate) fn take_fds(&mut self, count: usize) -> Vec<std::os::unix::io::RawFd> {
+		self.read_fds.drain(..count.min(self.read_fds.len())).collect()
+	}
+
+	pub(crate) fn recv(&mut self) -> std::io::Result<()> {
 		if self.read_end == self.read_buf.len() {
 			self.read_buf.resize(self.read_buf.len() * 2, 0);
 		}
 
-		let read = self.reader.read(&mut self.read_buf[self.read_end..])?;
+		let (read, fds) = self.reader.get_mut().read_with_fds(&mut self.read_buf[self.read_end..])?;
 		if read == 0 {
 			return Err(std::io::ErrorKind::UnexpectedEof.into());
 		}
 
 		self.read_end += read;
+		self.read_fds.extend(fds);
 
 		Ok(())
 	}
@@ -164,6 +229,473 @@ impl Connection {
 	}
 }
 
+/// Performs the DBUS_COOKIE_SHA1 SASL handshake and returns the server's GUID on success.
+fn sasl_auth_cookie_sha1(
+	reader: &mut std::io::BufReader<Stream>,
+	writer: &mut Stream,
+	read_buf: &mut Vec<u8>,
+	username: &str,
+) -> Result<Vec<u8>, ConnectError> {
+	use std::io::Write;
+
+	write!(writer, "\0AUTH DBUS_COOKIE_SHA1 {}\r\n", hex_encode(username.as_bytes())).map_err(ConnectError::Authenticate)?;
+	writer.flush().map_err(ConnectError::Authenticate)?;
+
+	let data_line = read_sasl_line(reader, read_buf)?;
+	let data =
+		data_line.strip_prefix(b"DATA ")
+		.ok_or_else(|| ConnectError::Authenticate(std::io::Error::other("malformed response")))?;
+	let data =
+		hex_decode(data)
+		.ok_or_else(|| ConnectError::Authenticate(std::io::Error::other("malformed response")))?;
+
+	let mut fields = data.split(|&b| b == b' ');
+	let cookie_context = fields.next().ok_or_else(|| ConnectError::Authenticate(std::io::Error::other("malformed response")))?;
+	let cookie_id = fields.next().ok_or_else(|| ConnectError::Authenticate(std::io::Error::other("malformed response")))?;
+	let server_challenge = fields.next().ok_or_else(|| ConnectError::Authenticate(std::io::Error::other("malformed response")))?;
+
+	if cookie_context.contains(&b'/') {
+		return Err(ConnectError::Authenticate(std::io::Error::other("cookie context contains a path separator")));
+	}
+
+	let cookie = read_cookie(cookie_context, cookie_id).map_err(ConnectError::Authenticate)?;
+
+	let mut client_challenge = [0_u8; 16];
+	fill_random(&mut client_challenge).map_err(ConnectError::Authenticate)?;
+	let client_challenge = hex_encode(&client_challenge);
+
+	let mut to_hash = vec![];
+	to_hash.extend_from_slice(server_challenge);
+	to_hash.push(b':');
+	to_hash.extend_from_slice(client_challenge.as_bytes());
+	to_hash.push(b':');
+	to_hash.extend_from_slice(&cookie);
+	let response_hash = hex_encode(&crate::sha1::digest(&to_hash));
+
+	write!(writer, "DATA {}\r\n", hex_encode(format!("{} {}", client_challenge, response_hash).as_bytes())).map_err(ConnectError::Authenticate)?;
+	writer.flush().map_err(ConnectError::Authenticate)?;
+
+	read_sasl_ok(reader, read_buf)
+}
+
+/// Reads `~/.dbus-keyrings/<cookie_context>` and returns the cookie with the given ID.
+fn read_cookie(cookie_context: &[u8], cookie_id: &[u8]) -> std::io::Result<Vec<u8>> {
+	use std::os::unix::ffi::OsStrExt;
+
+	let home = std::env::var_os("HOME").ok_or_else(|| std::io::Error::other("the HOME env var is not set"))?;
+
+	let mut keyring_path = std::path::PathBuf::from(home);
+	keyring_path.push(".dbus-keyrings");
+	keyring_path.push(std::ffi::OsStr::from_bytes(cookie_context));
+
+	let keyring = std::fs::read_to_string(keyring_path)?;
+
+	for line in keyring.lines() {
+		// Each line is `<cookie id> <creation timestamp> <cookie>`; the timestamp is not validated here.
+		let mut fields = line.splitn(3, ' ');
+		let id = fields.next();
+		let _timestamp = fields.next();
+		let cookie = fields.next();
+
+		if let (Some(id), Some(cookie)) = (id, cookie) {
+			if id.as_bytes() == cookie_id {
+				return Ok(cookie.as_bytes().to_owned());
+			}
+		}
+	}
+
+	Err(std::io::Error::other("no matching cookie found in keyring"))
+}
+
+/// Fills the given buffer with random bytes suitable for use as a SASL challenge.
+fn fill_random(buf: &mut [u8]) -> std::io::Result<()> {
+	let result = unsafe { libc::getrandom(buf.as_mut_ptr().cast(), buf.len(), 0) };
+	if result as usize != buf.len() {
+		return Err(std::io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+/// Reads a single `OK <guid>` SASL response line and returns the server's GUID.
+fn read_sasl_ok(reader: &mut std::io::BufReader<Stream>, read_buf: &mut Vec<u8>) -> Result<Vec<u8>, ConnectError> {
+	let line = read_sasl_line(reader, read_buf)?;
+
+	if let Some(server_guid) = line.strip_prefix(b"OK ") {
+		Ok(server_guid.to_owned())
+	}
+	else {
+		Err(ConnectError::Authenticate(std::io::Error::other("malformed response")))
+	}
+}
+
+/// Reads a single CRLF-terminated SASL response line, returning its content without the CRLF.
+fn read_sasl_line(reader: &mut std::io::BufReader<Stream>, read_buf: &mut Vec<u8>) -> Result<Vec<u8>, ConnectError> {
+	use std::io::BufRead;
+
+	read_buf.clear();
+
+	let _ = reader.read_until(b'\n', read_buf).map_err(ConnectError::Authenticate)?;
+	if read_buf.iter().rev().nth(1).copied() != Some(b'\r') {
+		return Err(ConnectError::Authenticate(std::io::Error::other("malformed response")));
+	}
+
+	read_buf.truncate(read_buf.len() - 2);
+
+	Ok(read_buf.clone())
+}
+
+/// Hex-encodes the given bytes using lowercase digits.
+fn hex_encode(bytes: &[u8]) -> String {
+	use std::fmt::Write;
+
+	let mut result = String::with_capacity(bytes.len() * 2);
+	for b in bytes {
+		let _ = write!(result, "{:02x}", b);
+	}
+	result
+}
+
+/// Hex-decodes the given bytes, returning `None` if they are not valid hex.
+fn hex_decode(hex: &[u8]) -> Option<Vec<u8>> {
+	if !hex.len().is_multiple_of(2) {
+		return None;
+	}
+
+	hex.chunks_exact(2)
+		.map(|pair| {
+			let hi = (pair[0] as char).to_digit(16)?;
+			let lo = (pair[1] as char).to_digit(16)?;
+			Some(((hi << 4) | lo) as u8)
+		})
+		.collect()
+}
+
+/// Sends `NEGOTIATE_UNIX_FD` and returns whether the server agreed to it via `AGREE_UNIX_FDS`.
+fn negotiate_unix_fd(
+	reader: &mut std::io::BufReader<Stream>,
+	writer: &mut Stream,
+	read_buf: &mut Vec<u8>,
+) -> Result<bool, ConnectError> {
+	use std::io::Write;
+
+	writer.write_all(b"NEGOTIATE_UNIX_FD\r\n").map_err(ConnectError::Authenticate)?;
+	writer.flush().map_err(ConnectError::Authenticate)?;
+
+	let line = read_sasl_line(reader, read_buf)?;
+	Ok(line == b"AGREE_UNIX_FDS")
+}
+
+/// The maximum number of file descriptors this crate will accept in a single `recvmsg` call.
+const MAX_FDS_PER_RECV: usize = 16;
+
+/// Sends `data` over `fd`, attaching `fds` as `SCM_RIGHTS` ancillary data on the first `sendmsg` call.
+fn send_with_fds(fd: std::os::unix::io::RawFd, data: &[u8], fds: &[std::os::unix::io::RawFd]) -> std::io::Result<usize> {
+	unsafe {
+		let mut iov = libc::iovec { iov_base: data.as_ptr() as *mut libc::c_void, iov_len: data.len() };
+
+		let mut control_buf;
+		let mut msg: libc::msghdr = std::mem::zeroed();
+		msg.msg_iov = &mut iov;
+		msg.msg_iovlen = 1;
+
+		if !fds.is_empty() {
+			let payload_len = std::mem::size_of_val(fds) as u32;
+			control_buf = vec![0_u8; libc::CMSG_SPACE(payload_len) as usize];
+			msg.msg_control = control_buf.as_mut_ptr().cast();
+			msg.msg_controllen = control_buf.len() as _;
+
+			let cmsg = libc::CMSG_FIRSTHDR(&msg);
+			(*cmsg).cmsg_len = libc::CMSG_LEN(payload_len) as _;
+			(*cmsg).cmsg_level = libc::SOL_SOCKET;
+			(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+			std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+		}
+
+		let result = libc::sendmsg(fd, &msg, 0);
+		if result < 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+
+		Ok(result as usize)
+	}
+}
+
+/// Receives data from `fd`, returning any file descriptors that arrived as `SCM_RIGHTS` ancillary data alongside it.
+///
+/// The caller takes ownership of the returned file descriptors and is responsible for closing them.
+fn recv_with_fds(fd: std::os::unix::io::RawFd, buf: &mut [u8]) -> std::io::Result<(usize, Vec<std::os::unix::io::RawFd>)> {
+	unsafe {
+		let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+
+		let control_space = libc::CMSG_SPACE((MAX_FDS_PER_RECV * std::mem::size_of::<std::os::unix::io::RawFd>()) as u32) as usize;
+		let mut control_buf = vec![0_u8; control_space];
+
+		let mut msg: libc::msghdr = std::mem::zeroed();
+		msg.msg_iov = &mut iov;
+		msg.msg_iovlen = 1;
+		msg.msg_control = control_buf.as_mut_ptr().cast();
+		msg.msg_controllen = control_buf.len() as _;
+
+		let result = libc::recvmsg(fd, &mut msg, 0);
+		if result < 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+
+		let mut fds = vec![];
+
+		let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+		while !cmsg.is_null() {
+			if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+				let payload_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+				let count = payload_len / std::mem::size_of::<std::os::unix::io::RawFd>();
+				let data_ptr = libc::CMSG_DATA(cmsg).cast::<std::os::unix::io::RawFd>();
+				for i in 0..count {
+					fds.push(*data_ptr.add(i));
+				}
+			}
+
+			cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+		}
+
+		if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+			// The control buffer (sized for MAX_FDS_PER_RECV) was too small to hold everything the peer
+			// sent in this one message, so some descriptors never made it into `fds` above -- the kernel
+			// drops (closes) whichever ones didn't fit rather than leaving them dangling, but that still
+			// means a descriptor the peer meant to pass us is gone. Close what we did receive and fail
+			// loudly rather than silently handing back an incomplete set.
+			for fd in fds {
+				libc::close(fd);
+			}
+			return Err(std::io::Error::other(format!(
+				"peer sent more than {} file descriptors in a single message; ancillary data was truncated",
+				MAX_FDS_PER_RECV,
+			)));
+		}
+
+		Ok((result as usize, fds))
+	}
+}
+
+/// The underlying transport of a [`Connection`].
+///
+/// The D-Bus address spec allows a connection to be established over different kinds of transports,
+/// so this wraps whichever kind of stream was actually used to connect to the bus.
+enum Stream {
+	Unix(std::os::unix::net::UnixStream),
+	Tcp(std::net::TcpStream),
+}
+
+impl Stream {
+	fn try_clone(&self) -> std::io::Result<Self> {
+		match self {
+			Stream::Unix(stream) => Ok(Stream::Unix(stream.try_clone()?)),
+			Stream::Tcp(stream) => Ok(Stream::Tcp(stream.try_clone()?)),
+		}
+	}
+
+	/// Writes as much of `data` as the stream accepts, attaching `fds` as ancillary data on the first
+	/// successful send. Only the `Unix` variant can carry file descriptors.
+	///
+	/// `data` and `fds` are advanced in place past whatever was actually sent, including when this
+	/// returns early with an error (eg `WouldBlock` on a non-blocking connection) -- so the caller can
+	/// always tell exactly how much got through and retry with what's left.
+	fn write_all_with_fds(&mut self, data: &mut &[u8], fds: &mut &[std::os::unix::io::RawFd]) -> std::io::Result<()> {
+		use std::io::Write;
+		use std::os::unix::io::AsRawFd;
+
+		match self {
+			Stream::Unix(stream) => {
+				let raw_fd = stream.as_raw_fd();
+
+				while !data.is_empty() {
+					let sent = send_with_fds(raw_fd, data, fds)?;
+					*data = &data[sent..];
+					*fds = &[];
+				}
+
+				Ok(())
+			},
+
+			Stream::Tcp(stream) => {
+				if !fds.is_empty() {
+					return Err(std::io::Error::other("cannot pass file descriptors over a tcp transport"));
+				}
+
+				while !data.is_empty() {
+					let sent = stream.write(data)?;
+					if sent == 0 {
+						return Err(std::io::ErrorKind::WriteZero.into());
+					}
+					*data = &data[sent..];
+				}
+
+				Ok(())
+			},
+		}
+	}
+
+	/// Reads into `buf`, returning any file descriptors that arrived alongside the data.
+	fn read_with_fds(&mut self, buf: &mut [u8]) -> std::io::Result<(usize, Vec<std::os::unix::io::RawFd>)> {
+		use std::io::Read;
+		use std::os::unix::io::AsRawFd;
+
+		match self {
+			Stream::Unix(stream) => recv_with_fds(stream.as_raw_fd(), buf),
+			Stream::Tcp(stream) => Ok((stream.read(buf)?, vec![])),
+		}
+	}
+
+	fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+		match self {
+			Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+			Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+		}
+	}
+}
+
+impl std::os::unix::io::AsRawFd for Stream {
+	fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+		match self {
+			Stream::Unix(stream) => stream.as_raw_fd(),
+			Stream::Tcp(stream) => stream.as_raw_fd(),
+		}
+	}
+}
+
+impl std::io::Read for Stream {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		match self {
+			Stream::Unix(stream) => stream.read(buf),
+			Stream::Tcp(stream) => stream.read(buf),
+		}
+	}
+}
+
+impl std::io::Write for Stream {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		match self {
+			Stream::Unix(stream) => stream.write(buf),
+			Stream::Tcp(stream) => stream.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		match self {
+			Stream::Unix(stream) => stream.flush(),
+			Stream::Tcp(stream) => stream.flush(),
+		}
+	}
+}
+
+/// Connects to every address in a semicolon-separated D-Bus server address list in turn,
+/// returning the first one that succeeds.
+fn connect_address(addresses: &str) -> Result<Stream, ConnectError> {
+	let mut errors = vec![];
+
+	for address in addresses.split(';') {
+		if address.is_empty() {
+			continue;
+		}
+
+		match connect_one_address(address) {
+			Ok(stream) => return Ok(stream),
+			Err(err) => errors.push(err),
+		}
+	}
+
+	Err(ConnectError::NoAddressesConnected(errors))
+}
+
+/// Connects to a single D-Bus server address, eg `unix:path=/run/dbus/system_bus_socket` or `tcp:host=localhost,port=1234`.
+fn connect_one_address(address: &str) -> Result<Stream, ConnectError> {
+	let (transport, params) =
+		address.split_once(':')
+		.ok_or_else(|| ConnectError::MalformedAddress(address.to_owned()))?;
+
+	let params: std::collections::HashMap<&str, &str> =
+		params.split(',')
+		.filter(|param| !param.is_empty())
+		.map(|param| param.split_once('=').ok_or_else(|| ConnectError::MalformedAddress(address.to_owned())))
+		.collect::<Result<_, _>>()?;
+
+	match transport {
+		"unix" =>
+			if let Some(&path) = params.get("path") {
+				let path = std::path::Path::new(path);
+				let stream =
+					std::os::unix::net::UnixStream::connect(path)
+					.map_err(|err| ConnectError::ConnectAddress { address: address.to_owned(), err })?;
+				Ok(Stream::Unix(stream))
+			}
+			else if let Some(&name) = params.get("abstract") {
+				let stream =
+					connect_unix_abstract(name.as_bytes())
+					.map_err(|err| ConnectError::ConnectAddress { address: address.to_owned(), err })?;
+				Ok(Stream::Unix(stream))
+			}
+			else {
+				Err(ConnectError::MalformedAddress(address.to_owned()))
+			},
+
+		"tcp" => {
+			let host = params.get("host").copied().unwrap_or("localhost");
+			let port =
+				params.get("port")
+				.ok_or_else(|| ConnectError::MalformedAddress(address.to_owned()))?
+				.parse::<u16>()
+				.map_err(|_| ConnectError::MalformedAddress(address.to_owned()))?;
+
+			let stream =
+				std::net::TcpStream::connect((host, port))
+				.map_err(|err| ConnectError::ConnectAddress { address: address.to_owned(), err })?;
+			Ok(Stream::Tcp(stream))
+		},
+
+		_ => Err(ConnectError::MalformedAddress(address.to_owned())),
+	}
+}
+
+/// Connects to a unix domain socket in the Linux abstract namespace, ie one whose name starts with a NUL byte
+/// instead of corresponding to a path on the filesystem. `std::os::unix::net::UnixStream` has no API for this,
+/// so the underlying syscalls are used directly.
+fn connect_unix_abstract(name: &[u8]) -> std::io::Result<std::os::unix::net::UnixStream> {
+	use std::os::unix::io::FromRawFd;
+
+	unsafe {
+		let fd = libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0);
+		if fd < 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+
+		let mut addr: libc::sockaddr_un = std::mem::zeroed();
+		addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+		if name.len() + 1 > addr.sun_path.len() {
+			libc::close(fd);
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "abstract socket name is too long"));
+		}
+
+		// The leading NUL byte (left in place by `zeroed()` above) puts the socket in the abstract namespace
+		// rather than the filesystem namespace.
+		for (dest, &src) in addr.sun_path[1..].iter_mut().zip(name) {
+			*dest = src as libc::c_char;
+		}
+
+		let addr_len =
+			(std::mem::size_of::<libc::sa_family_t>() + 1 + name.len()) as libc::socklen_t;
+
+		let result = libc::connect(fd, std::ptr::addr_of!(addr).cast(), addr_len);
+		if result < 0 {
+			let err = std::io::Error::last_os_error();
+			libc::close(fd);
+			return Err(err);
+		}
+
+		Ok(std::os::unix::net::UnixStream::from_raw_fd(fd))
+	}
+}
+
 /// An error from connecting to a message bus.
 #[derive(Debug)]
 pub enum ConnectError {
@@ -174,7 +706,16 @@ pub enum ConnectError {
 		err: std::io::Error,
 	},
 
-	SessionBusEnvVar(Option<std::ffi::OsString>),
+	ConnectAddress {
+		address: String,
+		err: std::io::Error,
+	},
+
+	MalformedAddress(String),
+
+	NoAddressesConnected(Vec<ConnectError>),
+
+	SessionBusEnvVar,
 }
 
 impl std::fmt::Display for ConnectError {
@@ -182,8 +723,10 @@ impl std::fmt::Display for ConnectError {
 		match self {
 			ConnectError::Authenticate(_) => f.write_str("could not authenticate with bus"),
 			ConnectError::Connect { bus_path, err: _ } => write!(f, "could not connect to bus path {}", bus_path.display()),
-			ConnectError::SessionBusEnvVar(None) => f.write_str("the DBUS_SESSION_BUS_ADDRESS env var is not set"),
-			ConnectError::SessionBusEnvVar(Some(value)) => write!(f, "the DBUS_SESSION_BUS_ADDRESS env var is malformed: {:?}", value),
+			ConnectError::ConnectAddress { address, err: _ } => write!(f, "could not connect to bus address {:?}", address),
+			ConnectError::MalformedAddress(address) => write!(f, "malformed bus address {:?}", address),
+			ConnectError::NoAddressesConnected(_) => f.write_str("could not connect to any of the bus's addresses"),
+			ConnectError::SessionBusEnvVar => f.write_str("the DBUS_SESSION_BUS_ADDRESS env var is not set"),
 		}
 	}
 }
@@ -194,7 +737,10 @@ impl std::error::Error for ConnectError {
 		match self {
 			ConnectError::Authenticate(err) => Some(err),
 			ConnectError::Connect { bus_path: _, err } => Some(err),
-			ConnectError::SessionBusEnvVar(_) => None,
+			ConnectError::ConnectAddress { address: _, err } => Some(err),
+			ConnectError::MalformedAddress(_) => None,
+			ConnectError::NoAddressesConnected(errs) => errs.first().map(|err| err as _),
+			ConnectError::SessionBusEnvVar => None,
 		}
 	}
-}
\ No newline at end of file
+}
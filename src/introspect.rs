@@ -0,0 +1,356 @@
+//! Parsing `org.freedesktop.DBus.Introspectable.Introspect` XML, and a typed [`Proxy`] built on top of it.
+
+use crate::{
+	client::{Client, ClientError},
+	types::{ObjectPath, Signature, Variant},
+};
+
+/// The interfaces exposed by an object, as described by its introspection XML.
+#[derive(Clone, Debug)]
+pub struct Node {
+	pub interfaces: Vec<Interface>,
+}
+
+/// A single interface, eg `org.mpris.MediaPlayer2.Player`.
+#[derive(Clone, Debug)]
+pub struct Interface {
+	pub name: String,
+	pub methods: Vec<Method>,
+	pub signals: Vec<Signal>,
+	pub properties: Vec<Property>,
+}
+
+/// A method on an [`Interface`].
+#[derive(Clone, Debug)]
+pub struct Method {
+	pub name: String,
+	pub args: Vec<Arg>,
+}
+
+/// A signal on an [`Interface`].
+#[derive(Clone, Debug)]
+pub struct Signal {
+	pub name: String,
+	pub args: Vec<Arg>,
+}
+
+/// Whether an [`Arg`] is passed to the method or returned from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgDirection {
+	In,
+	Out,
+}
+
+/// A single method or signal argument.
+#[derive(Clone, Debug)]
+pub struct Arg {
+	pub name: Option<String>,
+	pub direction: ArgDirection,
+	pub signature: Signature,
+}
+
+/// Whether a [`Property`] can be read, written, or both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyAccess {
+	Read,
+	Write,
+	ReadWrite,
+}
+
+/// A property on an [`Interface`], as served by `org.freedesktop.DBus.Properties`.
+#[derive(Clone, Debug)]
+pub struct Property {
+	pub name: String,
+	pub signature: Signature,
+	pub access: PropertyAccess,
+}
+
+struct PartialInterface {
+	name: String,
+	methods: Vec<Method>,
+	signals: Vec<Signal>,
+	properties: Vec<Property>,
+}
+
+struct PartialCallable {
+	name: String,
+	args: Vec<Arg>,
+}
+
+/// Parses the XML returned by `org.freedesktop.DBus.Introspectable.Introspect` into a [`Node`].
+pub fn parse(xml: &str) -> Result<Node, IntrospectParseError> {
+	let mut interfaces = vec![];
+
+	let mut current_interface: Option<PartialInterface> = None;
+	let mut current_method: Option<PartialCallable> = None;
+	let mut current_signal: Option<PartialCallable> = None;
+
+	let mut pos = 0;
+	while let Some(relative_start) = xml[pos..].find('<') {
+		let start = pos + relative_start;
+		let end = xml[start..].find('>').ok_or(IntrospectParseError::UnexpectedEnd)? + start;
+		let content = &xml[(start + 1)..end];
+		pos = end + 1;
+
+		if content.starts_with('!') || content.starts_with('?') {
+			continue;
+		}
+
+		if let Some(name) = content.strip_prefix('/') {
+			match name.trim() {
+				"interface" => {
+					let interface = current_interface.take().ok_or(IntrospectParseError::MalformedXml)?;
+					interfaces.push(Interface {
+						name: interface.name,
+						methods: interface.methods,
+						signals: interface.signals,
+						properties: interface.properties,
+					});
+				},
+
+				"method" => {
+					let method = current_method.take().ok_or(IntrospectParseError::MalformedXml)?;
+					current_interface.as_mut().ok_or(IntrospectParseError::MalformedXml)?
+						.methods.push(Method { name: method.name, args: method.args });
+				},
+
+				"signal" => {
+					let signal = current_signal.take().ok_or(IntrospectParseError::MalformedXml)?;
+					current_interface.as_mut().ok_or(IntrospectParseError::MalformedXml)?
+						.signals.push(Signal { name: signal.name, args: signal.args });
+				},
+
+				_ => {},
+			}
+			continue;
+		}
+
+		let self_closing = content.trim_end().ends_with('/');
+		let content = if self_closing { content.trim_end().trim_end_matches('/') } else { content };
+		let (tag_name, attrs) = parse_tag(content)?;
+
+		match tag_name {
+			"interface" => {
+				let name = attr(&attrs, "name").ok_or(IntrospectParseError::MalformedXml)?.to_owned();
+				current_interface = Some(PartialInterface { name, methods: vec![], signals: vec![], properties: vec![] });
+			},
+
+			"method" => {
+				let name = attr(&attrs, "name").ok_or(IntrospectParseError::MalformedXml)?.to_owned();
+				current_method = Some(PartialCallable { name, args: vec![] });
+				if self_closing {
+					let method = current_method.take().unwrap();
+					current_interface.as_mut().ok_or(IntrospectParseError::MalformedXml)?
+						.methods.push(Method { name: method.name, args: method.args });
+				}
+			},
+
+			"signal" => {
+				let name = attr(&attrs, "name").ok_or(IntrospectParseError::MalformedXml)?.to_owned();
+				current_signal = Some(PartialCallable { name, args: vec![] });
+				if self_closing {
+					let signal = current_signal.take().unwrap();
+					current_interface.as_mut().ok_or(IntrospectParseError::MalformedXml)?
+						.signals.push(Signal { name: signal.name, args: signal.args });
+				}
+			},
+
+			"property" => {
+				let name = attr(&attrs, "name").ok_or(IntrospectParseError::MalformedXml)?.to_owned();
+				let signature = parse_single_signature(attr(&attrs, "type").ok_or(IntrospectParseError::MalformedXml)?)?;
+				let access = match attr(&attrs, "access") {
+					Some("read") => PropertyAccess::Read,
+					Some("write") => PropertyAccess::Write,
+					Some("readwrite") | None => PropertyAccess::ReadWrite,
+					Some(_) => return Err(IntrospectParseError::MalformedXml),
+				};
+				current_interface.as_mut().ok_or(IntrospectParseError::MalformedXml)?
+					.properties.push(Property { name, signature, access });
+			},
+
+			"arg" => {
+				let name = attr(&attrs, "name").map(str::to_owned);
+				let signature = parse_single_signature(attr(&attrs, "type").ok_or(IntrospectParseError::MalformedXml)?)?;
+				let direction = match attr(&attrs, "direction") {
+					Some("out") => ArgDirection::Out,
+					_ => ArgDirection::In,
+				};
+				let arg = Arg { name, direction, signature };
+				if let Some(signal) = current_signal.as_mut() {
+					signal.args.push(arg);
+				}
+				else if let Some(method) = current_method.as_mut() {
+					method.args.push(arg);
+				}
+			},
+
+			_ => {},
+		}
+	}
+
+	Ok(Node { interfaces })
+}
+
+type TagAttrs<'a> = Vec<(&'a str, String)>;
+
+fn parse_tag(content: &str) -> Result<(&str, TagAttrs<'_>), IntrospectParseError> {
+	let content = content.trim();
+	let name_end = content.find(char::is_whitespace).unwrap_or(content.len());
+	let name = &content[..name_end];
+
+	let mut attrs = vec![];
+	let mut rest = content[name_end..].trim_start();
+	while !rest.is_empty() {
+		let eq = rest.find('=').ok_or(IntrospectParseError::MalformedXml)?;
+		let attr_name = rest[..eq].trim();
+		rest = &rest[(eq + 1)..];
+
+		let quote = rest.chars().next().ok_or(IntrospectParseError::MalformedXml)?;
+		if quote != '"' && quote != '\'' {
+			return Err(IntrospectParseError::MalformedXml);
+		}
+		rest = &rest[1..];
+
+		let value_end = rest.find(quote).ok_or(IntrospectParseError::MalformedXml)?;
+		attrs.push((attr_name, unescape(&rest[..value_end])));
+		rest = rest[(value_end + 1)..].trim_start();
+	}
+
+	Ok((name, attrs))
+}
+
+fn attr<'a>(attrs: &'a [(&str, String)], name: &str) -> Option<&'a str> {
+	attrs.iter().find(|(attr_name, _)| *attr_name == name).map(|(_, value)| value.as_str())
+}
+
+fn unescape(s: &str) -> String {
+	s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+fn parse_single_signature(s: &str) -> Result<Signature, IntrospectParseError> {
+	let mut signatures = Signature::parse(s).map_err(|_| IntrospectParseError::MalformedXml)?;
+	if signatures.len() != 1 {
+		return Err(IntrospectParseError::MalformedXml);
+	}
+	Ok(signatures.remove(0))
+}
+
+/// An error parsing introspection XML with [`parse`].
+#[derive(Debug)]
+pub enum IntrospectParseError {
+	UnexpectedEnd,
+	MalformedXml,
+}
+
+impl std::fmt::Display for IntrospectParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			IntrospectParseError::UnexpectedEnd => f.write_str("introspection XML ended unexpectedly"),
+			IntrospectParseError::MalformedXml => f.write_str("malformed introspection XML"),
+		}
+	}
+}
+
+impl std::error::Error for IntrospectParseError {}
+
+/// A typed wrapper around a [`Client`], a single object path, and one of its interfaces, built by
+/// introspecting the object. Validates argument signatures against the interface's declared methods
+/// before marshalling a call.
+pub struct Proxy<'a> {
+	client: &'a mut Client,
+	destination: String,
+	path: ObjectPath,
+	interface: Interface,
+}
+
+impl<'a> Proxy<'a> {
+	/// Calls `Introspect` on `destination`+`path` and returns a `Proxy` for the named interface.
+	pub fn introspect(client: &'a mut Client, destination: String, path: ObjectPath, interface_name: &str) -> Result<Self, ProxyError> {
+		let xml =
+			client.method_call(
+				destination.clone(),
+				path.clone(),
+				"org.freedesktop.DBus.Introspectable".to_owned(),
+				"Introspect".to_owned(),
+				None,
+			)
+			.map_err(ProxyError::Client)?
+			.ok_or(ProxyError::UnexpectedResponse)?
+			.into_string()
+			.map_err(|_| ProxyError::UnexpectedResponse)?;
+
+		let node = parse(&xml).map_err(ProxyError::Parse)?;
+		let interface =
+			node.interfaces.into_iter().find(|interface| interface.name == interface_name)
+			.ok_or_else(|| ProxyError::UnknownInterface(interface_name.to_owned()))?;
+
+		Ok(Proxy { client, destination, path, interface })
+	}
+
+	/// Calls a method on this interface, validating `args`'s signature against the method's declared
+	/// `in` arguments before marshalling.
+	pub fn call(&mut self, method_name: &str, args: Option<&Variant>) -> Result<Option<Variant>, ProxyError> {
+		let method =
+			self.interface.methods.iter().find(|method| method.name == method_name)
+			.ok_or_else(|| ProxyError::UnknownMethod(method_name.to_owned()))?;
+
+		let expected_signatures: Vec<Signature> =
+			method.args.iter().filter(|arg| arg.direction == ArgDirection::In).map(|arg| arg.signature.clone()).collect();
+
+		// Flatten `args` the same way `Message::serialize` flattens a body: a `Variant::Tuple` is several
+		// top-level arguments, any other value (or `None`) is zero or one -- so a natural single-argument
+		// call like `Some(&Variant::String(x))` is compared against a one-element expected signature
+		// instead of being rejected for not already being wrapped in a `Variant::Tuple`.
+		let actual_signatures: Vec<Signature> = match args {
+			Some(Variant::Tuple { elements }) => elements.iter().map(Variant::signature).collect(),
+			Some(other) => vec![other.signature()],
+			None => vec![],
+		};
+
+		if actual_signatures != expected_signatures {
+			return Err(ProxyError::SignatureMismatch {
+				expected: Signature::Tuple(expected_signatures),
+				actual: Signature::Tuple(actual_signatures),
+			});
+		}
+
+		self.client
+			.method_call(self.destination.clone(), self.path.clone(), self.interface.name.clone(), method_name.to_owned(), args)
+			.map_err(ProxyError::Client)
+	}
+}
+
+/// An error using a [`Proxy`].
+#[derive(Debug)]
+pub enum ProxyError {
+	Client(ClientError),
+	Parse(IntrospectParseError),
+	UnexpectedResponse,
+	UnknownInterface(String),
+	UnknownMethod(String),
+	SignatureMismatch { expected: Signature, actual: Signature },
+}
+
+impl std::fmt::Display for ProxyError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ProxyError::Client(_) => f.write_str("error communicating with bus"),
+			ProxyError::Parse(_) => f.write_str("could not parse introspection XML"),
+			ProxyError::UnexpectedResponse => f.write_str("bus sent an unexpected response"),
+			ProxyError::UnknownInterface(name) => write!(f, "interface {} not found in introspection data", name),
+			ProxyError::UnknownMethod(name) => write!(f, "method {} not found on interface", name),
+			ProxyError::SignatureMismatch { expected, actual } => write!(f, "expected signature {}, got {}", expected, actual),
+		}
+	}
+}
+
+impl std::error::Error for ProxyError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ProxyError::Client(err) => Some(err),
+			ProxyError::Parse(err) => Some(err),
+			ProxyError::UnexpectedResponse | ProxyError::UnknownInterface(_) | ProxyError::UnknownMethod(_) | ProxyError::SignatureMismatch { .. } =>
+				None,
+		}
+	}
+}
@@ -0,0 +1,8 @@
+pub mod async_client;
+pub mod client;
+pub mod conn;
+pub mod introspect;
+mod message;
+pub mod server;
+mod sha1;
+pub mod types;
@@ -0,0 +1,604 @@
+//! Marshalling and unmarshalling of D-Bus messages to and from the wire format.
+//!
+//! Messages are always sent in little-endian byte order, whatever the host's byte order, and only
+//! little-endian messages can be received.
+
+use std::os::unix::io::RawFd;
+
+use crate::types::{ObjectPath, Signature, Variant};
+
+/// The type of a [`Message`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MessageType {
+	MethodCall,
+	MethodReturn,
+	Error,
+	Signal,
+}
+
+impl MessageType {
+	fn from_wire(b: u8) -> Option<Self> {
+		match b {
+			1 => Some(MessageType::MethodCall),
+			2 => Some(MessageType::MethodReturn),
+			3 => Some(MessageType::Error),
+			4 => Some(MessageType::Signal),
+			_ => None,
+		}
+	}
+
+	fn to_wire(self) -> u8 {
+		match self {
+			MessageType::MethodCall => 1,
+			MessageType::MethodReturn => 2,
+			MessageType::Error => 3,
+			MessageType::Signal => 4,
+		}
+	}
+}
+
+/// A D-Bus message, ie the unit of communication sent and received over a [`crate::conn::Connection`].
+#[derive(Clone, Debug)]
+pub(crate) struct Message {
+	pub(crate) r#type: MessageType,
+	pub(crate) flags: u8,
+	pub(crate) serial: u32,
+	pub(crate) path: Option<ObjectPath>,
+	pub(crate) interface: Option<String>,
+	pub(crate) member: Option<String>,
+	pub(crate) error_name: Option<String>,
+	pub(crate) reply_serial: Option<u32>,
+	pub(crate) destination: Option<String>,
+	pub(crate) sender: Option<String>,
+	pub(crate) body: Option<Variant>,
+}
+
+/// No-reply-expected, the only message flag this crate currently sets or reads.
+pub(crate) const FLAG_NO_REPLY_EXPECTED: u8 = 0x1;
+
+impl Message {
+	/// Serializes the message, appending the wire bytes to `buf`. Any `Variant::UnixFd` values in the body
+	/// are appended to `fds` in encounter order, which is also the order their wire indices refer to;
+	/// the caller is responsible for handing `fds` to the [`crate::conn::Connection`] alongside `buf`.
+	pub(crate) fn serialize(&self, buf: &mut Vec<u8>, fds: &mut Vec<RawFd>) {
+		let mut body = vec![];
+		if let Some(body_value) = &self.body {
+			Serializer { buf: &mut body, fds }.write_value(body_value);
+		}
+
+		buf.push(b'l'); // little-endian
+		buf.push(self.r#type.to_wire());
+		buf.push(self.flags);
+		buf.push(1); // protocol version
+
+		buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&self.serial.to_le_bytes());
+
+		let mut header_fields = vec![];
+		{
+			let mut unused_fds = vec![];
+			let mut ser = Serializer { buf: &mut header_fields, fds: &mut unused_fds };
+
+			if let Some(path) = &self.path {
+				ser.write_header_field(1, &Variant::ObjectPath(path.clone()));
+			}
+			if let Some(interface) = &self.interface {
+				ser.write_header_field(2, &Variant::String(interface.clone()));
+			}
+			if let Some(member) = &self.member {
+				ser.write_header_field(3, &Variant::String(member.clone()));
+			}
+			if let Some(error_name) = &self.error_name {
+				ser.write_header_field(4, &Variant::String(error_name.clone()));
+			}
+			if let Some(reply_serial) = self.reply_serial {
+				ser.write_header_field(5, &Variant::U32(reply_serial));
+			}
+			if let Some(destination) = &self.destination {
+				ser.write_header_field(6, &Variant::String(destination.clone()));
+			}
+			if let Some(body_value) = &self.body {
+				// The body signature is the flat concatenation of each top-level argument's type, eg `"su"`
+				// for a two-argument body -- not a single STRUCT type, so a `Variant::Tuple` body (used to
+				// represent a multi-argument call or reply) is flattened rather than going through its own
+				// parenthesized `Signature::Tuple`.
+				let body_signature = match body_value {
+					Variant::Tuple { elements } => elements.iter().map(Variant::signature).collect(),
+					other => vec![other.signature()],
+				};
+				ser.write_header_field(8, &Variant::Signature(body_signature));
+			}
+			if !fds.is_empty() {
+				ser.write_header_field(9, &Variant::U32(fds.len() as u32));
+			}
+		}
+
+		buf.extend_from_slice(&(header_fields.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&header_fields);
+
+		while !buf.len().is_multiple_of(8) {
+			buf.push(0);
+		}
+
+		buf.extend_from_slice(&body);
+	}
+
+	/// Attempts to parse a complete message from the front of `buf`, resolving any UNIX_FDS references against
+	/// `available_fds`. Returns the message, the number of bytes consumed, and the number of leading entries of
+	/// `available_fds` that were claimed by the message body (the caller should remove these from its queue),
+	/// or `None` if `buf` does not yet hold a complete message.
+	pub(crate) fn deserialize(buf: &[u8], available_fds: &[RawFd]) -> Result<Option<(Message, usize, usize)>, MessageParseError> {
+		if buf.len() < 16 {
+			return Ok(None);
+		}
+
+		if buf[0] != b'l' {
+			return Err(MessageParseError::UnsupportedEndianness(buf[0]));
+		}
+
+		let r#type = MessageType::from_wire(buf[1]).ok_or(MessageParseError::UnknownMessageType(buf[1]))?;
+		let flags = buf[2];
+		let body_len = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+		let serial = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+		let header_fields_len = u32::from_le_bytes(buf[12..16].try_into().unwrap()) as usize;
+
+		let header_fields_start = 16;
+		let header_fields_end = header_fields_start + header_fields_len;
+		let body_start = { let mut end = header_fields_end; while !end.is_multiple_of(8) { end += 1; } end };
+		let body_end = body_start + body_len;
+
+		if buf.len() < body_end {
+			return Ok(None);
+		}
+
+		let mut de = Deserializer { buf: &buf[header_fields_start..header_fields_end], pos: 0, fds: &[] };
+
+		let mut path = None;
+		let mut interface = None;
+		let mut member = None;
+		let mut error_name = None;
+		let mut reply_serial = None;
+		let mut destination = None;
+		let mut sender = None;
+		let mut signature = None;
+		let mut num_fds = 0;
+
+		while de.pos < de.buf.len() {
+			de.align(8);
+			if de.pos >= de.buf.len() {
+				break;
+			}
+			let code = de.read_u8()?;
+			let value_signature = de.read_signature_single()?;
+			let value = de.read_value(&value_signature)?;
+			match code {
+				1 => path = Some(value.into_object_path().map_err(|_| MessageParseError::MalformedHeader)?),
+				2 => interface = Some(value.into_string().map_err(|_| MessageParseError::MalformedHeader)?),
+				3 => member = Some(value.into_string().map_err(|_| MessageParseError::MalformedHeader)?),
+				4 => error_name = Some(value.into_string().map_err(|_| MessageParseError::MalformedHeader)?),
+				5 => reply_serial = Some(value.into_u32().map_err(|_| MessageParseError::MalformedHeader)?),
+				6 => destination = Some(value.into_string().map_err(|_| MessageParseError::MalformedHeader)?),
+				7 => sender = Some(value.into_string().map_err(|_| MessageParseError::MalformedHeader)?),
+				8 => signature = Some(value.into_signature().map_err(|_| MessageParseError::MalformedHeader)?),
+				9 => num_fds = value.into_u32().map_err(|_| MessageParseError::MalformedHeader)?,
+				_ => {}, // unknown header fields are ignored, per the spec
+			}
+		}
+
+		if (num_fds as usize) > available_fds.len() {
+			return Ok(None);
+		}
+		let message_fds = &available_fds[..num_fds as usize];
+
+		// The body signature is zero or more top-level complete types, not a single STRUCT type. A body
+		// with exactly one argument is the bare value (matching how callers pass a single-argument body
+		// without wrapping it); two or more are collected into a `Variant::Tuple`, matching how callers
+		// construct a multi-argument body.
+		let body = match signature {
+			Some(elements) => {
+				let mut de = Deserializer { buf: &buf[body_start..body_end], pos: 0, fds: message_fds };
+				let mut values = vec![];
+				for element in &elements {
+					values.push(de.read_value(element)?);
+				}
+				match values.len() {
+					0 => None,
+					1 => Some(values.remove(0)),
+					_ => Some(Variant::Tuple { elements: values }),
+				}
+			},
+			None => None,
+		};
+
+		Ok(Some((
+			Message {
+				r#type,
+				flags,
+				serial,
+				path,
+				interface,
+				member,
+				error_name,
+				reply_serial,
+				destination,
+				sender,
+				body,
+			},
+			body_end,
+			num_fds as usize,
+		)))
+	}
+}
+
+struct Serializer<'a> {
+	buf: &'a mut Vec<u8>,
+	fds: &'a mut Vec<RawFd>,
+}
+
+impl Serializer<'_> {
+	fn align(&mut self, alignment: usize) {
+		while !self.buf.len().is_multiple_of(alignment) {
+			self.buf.push(0);
+		}
+	}
+
+	fn write_header_field(&mut self, code: u8, value: &Variant) {
+		self.align(8);
+		self.buf.push(code);
+		self.write_value(&Variant::Variant(Box::new(value.clone())));
+	}
+
+	fn write_string_like(&mut self, s: &str) {
+		self.align(4);
+		self.buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+		self.buf.extend_from_slice(s.as_bytes());
+		self.buf.push(0);
+	}
+
+	fn write_value(&mut self, value: &Variant) {
+		match value {
+			Variant::Byte(v) => self.buf.push(*v),
+
+			Variant::Boolean(v) => { self.align(4); self.buf.extend_from_slice(&(*v as u32).to_le_bytes()); },
+
+			Variant::I16(v) => { self.align(2); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::U16(v) => { self.align(2); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::I32(v) => { self.align(4); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::U32(v) => { self.align(4); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::I64(v) => { self.align(8); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::U64(v) => { self.align(8); self.buf.extend_from_slice(&v.to_le_bytes()); },
+			Variant::Double(v) => { self.align(8); self.buf.extend_from_slice(&v.to_le_bytes()); },
+
+			Variant::String(s) => self.write_string_like(s),
+			Variant::ObjectPath(p) => self.write_string_like(&p.0),
+
+			Variant::Signature(sigs) => {
+				let s: String = sigs.iter().map(Signature::to_string).collect();
+				self.buf.push(s.len() as u8);
+				self.buf.extend_from_slice(s.as_bytes());
+				self.buf.push(0);
+			},
+
+			Variant::Variant(inner) => {
+				let s = inner.signature().to_string();
+				self.buf.push(s.len() as u8);
+				self.buf.extend_from_slice(s.as_bytes());
+				self.buf.push(0);
+				self.write_value(inner);
+			},
+
+			Variant::UnixFd(fd) => {
+				self.align(4);
+				let index = self.fds.len() as u32;
+				self.fds.push(*fd);
+				self.buf.extend_from_slice(&index.to_le_bytes());
+			},
+
+			Variant::Array { element_signature, elements } => {
+				self.align(4);
+				let len_pos = self.buf.len();
+				self.buf.extend_from_slice(&0_u32.to_le_bytes());
+				self.align(element_signature.alignment());
+				let start = self.buf.len();
+				for element in elements {
+					self.write_value(element);
+				}
+				let len = (self.buf.len() - start) as u32;
+				self.buf[len_pos..(len_pos + 4)].copy_from_slice(&len.to_le_bytes());
+			},
+
+			Variant::Tuple { elements } => {
+				self.align(8);
+				for element in elements {
+					self.write_value(element);
+				}
+			},
+
+			Variant::Dict { elements, .. } => {
+				self.align(4);
+				let len_pos = self.buf.len();
+				self.buf.extend_from_slice(&0_u32.to_le_bytes());
+				self.align(8);
+				let start = self.buf.len();
+				for (key, value) in elements {
+					self.align(8);
+					self.write_value(key);
+					self.write_value(value);
+				}
+				let len = (self.buf.len() - start) as u32;
+				self.buf[len_pos..(len_pos + 4)].copy_from_slice(&len.to_le_bytes());
+			},
+		}
+	}
+}
+
+struct Deserializer<'a> {
+	buf: &'a [u8],
+	pos: usize,
+	fds: &'a [RawFd],
+}
+
+impl Deserializer<'_> {
+	fn align(&mut self, alignment: usize) {
+		while !self.pos.is_multiple_of(alignment) {
+			self.pos += 1;
+		}
+	}
+
+	/// Takes the next `len` bytes at the current position, advancing past them, or errors if `buf`
+	/// doesn't have that many bytes left -- the single chokepoint every other read goes through so a
+	/// crafted message with an inconsistent or oversized length never indexes or slices out of bounds.
+	fn take(&mut self, len: usize) -> Result<&[u8], MessageParseError> {
+		let end = self.pos.checked_add(len).ok_or(MessageParseError::MalformedHeader)?;
+		let bytes = self.buf.get(self.pos..end).ok_or(MessageParseError::MalformedHeader)?;
+		self.pos = end;
+		Ok(bytes)
+	}
+
+	fn read_u8(&mut self) -> Result<u8, MessageParseError> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn read_u32(&mut self) -> Result<u32, MessageParseError> {
+		self.align(4);
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	fn read_string_like(&mut self) -> Result<String, MessageParseError> {
+		let len = self.read_u32()? as usize;
+		let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+		self.take(1)?; // skip the trailing NUL
+		Ok(s)
+	}
+
+	/// Reads a signature string's complete types, eg `"su"` reads as two elements.
+	fn read_signature(&mut self) -> Result<Vec<Signature>, MessageParseError> {
+		let len = self.read_u8()? as usize;
+		let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+		self.take(1)?;
+		Signature::parse(&s).map_err(|_| MessageParseError::MalformedHeader)
+	}
+
+	/// Reads a signature string that must contain exactly one complete type, eg a `VARIANT` value's
+	/// inline signature or a header field's declared value type.
+	fn read_signature_single(&mut self) -> Result<Signature, MessageParseError> {
+		let mut signatures = self.read_signature()?;
+		if signatures.len() != 1 {
+			return Err(MessageParseError::MalformedHeader);
+		}
+		Ok(signatures.remove(0))
+	}
+
+	fn read_value(&mut self, signature: &Signature) -> Result<Variant, MessageParseError> {
+		Ok(match signature {
+			Signature::Byte => Variant::Byte(self.read_u8()?),
+
+			Signature::Boolean => Variant::Boolean(self.read_u32()? != 0),
+
+			Signature::I16 => { self.align(2); Variant::I16(i16::from_le_bytes(self.take(2)?.try_into().unwrap())) },
+			Signature::U16 => { self.align(2); Variant::U16(u16::from_le_bytes(self.take(2)?.try_into().unwrap())) },
+			Signature::I32 => Variant::I32(self.read_u32()? as i32),
+			Signature::U32 => Variant::U32(self.read_u32()?),
+			Signature::I64 => { self.align(8); Variant::I64(i64::from_le_bytes(self.take(8)?.try_into().unwrap())) },
+			Signature::U64 => { self.align(8); Variant::U64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) },
+			Signature::Double => { self.align(8); Variant::Double(f64::from_le_bytes(self.take(8)?.try_into().unwrap())) },
+
+			Signature::String => Variant::String(self.read_string_like()?),
+			Signature::ObjectPath => Variant::ObjectPath(ObjectPath(self.read_string_like()?)),
+			Signature::Signature => Variant::Signature(self.read_signature()?),
+			Signature::UnixFd => {
+				let index = self.read_u32()? as usize;
+				let fd = *self.fds.get(index).ok_or(MessageParseError::MalformedHeader)?;
+				Variant::UnixFd(fd)
+			},
+
+			Signature::Variant => {
+				let inner_signature = self.read_signature_single()?;
+				Variant::Variant(Box::new(self.read_value(&inner_signature)?))
+			},
+
+			Signature::Array(element_signature) => {
+				let len = self.read_u32()? as usize;
+				self.align(element_signature.alignment());
+				let end = self.pos.checked_add(len).ok_or(MessageParseError::MalformedHeader)?;
+				if end > self.buf.len() {
+					return Err(MessageParseError::MalformedHeader);
+				}
+				let mut elements = vec![];
+				while self.pos < end {
+					elements.push(self.read_value(element_signature)?);
+				}
+				Variant::Array { element_signature: (**element_signature).clone(), elements }
+			},
+
+			Signature::Tuple(element_signatures) => {
+				self.align(8);
+				let mut elements = vec![];
+				for element_signature in element_signatures {
+					elements.push(self.read_value(element_signature)?);
+				}
+				Variant::Tuple { elements }
+			},
+
+			Signature::Dict(key_signature, value_signature) => {
+				let len = self.read_u32()? as usize;
+				self.align(8);
+				let end = self.pos.checked_add(len).ok_or(MessageParseError::MalformedHeader)?;
+				if end > self.buf.len() {
+					return Err(MessageParseError::MalformedHeader);
+				}
+				let mut elements = vec![];
+				while self.pos < end {
+					self.align(8);
+					let key = self.read_value(key_signature)?;
+					let value = self.read_value(value_signature)?;
+					elements.push((key, value));
+				}
+				Variant::Dict { key_signature: (**key_signature).clone(), value_signature: (**value_signature).clone(), elements }
+			},
+		})
+	}
+}
+
+/// An error parsing a [`Message`] from bytes read off a [`crate::conn::Connection`].
+#[derive(Debug)]
+pub enum MessageParseError {
+	UnsupportedEndianness(u8),
+	UnknownMessageType(u8),
+	MalformedHeader,
+}
+
+impl std::fmt::Display for MessageParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MessageParseError::UnsupportedEndianness(b) => write!(f, "unsupported message endianness {:#04x}", b),
+			MessageParseError::UnknownMessageType(b) => write!(f, "unknown message type {}", b),
+			MessageParseError::MalformedHeader => f.write_str("malformed message header"),
+		}
+	}
+}
+
+impl std::error::Error for MessageParseError {}
+
+#[cfg(test)]
+mod tests {
+	use super::{Message, MessageParseError, MessageType};
+	use crate::types::{ObjectPath, Variant};
+
+	fn roundtrip(message: &Message) -> Message {
+		let mut buf = vec![];
+		let mut fds = vec![];
+		message.serialize(&mut buf, &mut fds);
+		let (parsed, consumed, _num_fds) = Message::deserialize(&buf, &[]).unwrap().unwrap();
+		assert_eq!(consumed, buf.len());
+		parsed
+	}
+
+	#[test]
+	fn roundtrip_multi_argument_method_call() {
+		let message = Message {
+			r#type: MessageType::MethodCall,
+			flags: 0,
+			serial: 7,
+			path: Some(ObjectPath("/com/example/Object".to_owned())),
+			interface: Some("com.example.Interface".to_owned()),
+			member: Some("DoThing".to_owned()),
+			error_name: None,
+			reply_serial: None,
+			destination: Some("com.example.Destination".to_owned()),
+			sender: None,
+			body: Some(Variant::Tuple { elements: vec![Variant::String("hello".to_owned()), Variant::U32(42)] }),
+		};
+
+		let parsed = roundtrip(&message);
+		assert_eq!(parsed.r#type, MessageType::MethodCall);
+		assert_eq!(parsed.serial, 7);
+		assert_eq!(parsed.path, message.path);
+		assert_eq!(parsed.interface, message.interface);
+		assert_eq!(parsed.member, message.member);
+		assert_eq!(parsed.destination, message.destination);
+		match parsed.body {
+			Some(Variant::Tuple { elements }) => {
+				match &elements[..] {
+					[Variant::String(s), Variant::U32(n)] => {
+						assert_eq!(s, "hello");
+						assert_eq!(*n, 42);
+					},
+					other => panic!("unexpected body elements: {:?}", other),
+				}
+			},
+			other => panic!("unexpected body: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn roundtrip_no_body() {
+		let message = Message {
+			r#type: MessageType::Signal,
+			flags: 0,
+			serial: 1,
+			path: Some(ObjectPath("/com/example/Object".to_owned())),
+			interface: Some("com.example.Interface".to_owned()),
+			member: Some("Pinged".to_owned()),
+			error_name: None,
+			reply_serial: None,
+			destination: None,
+			sender: None,
+			body: None,
+		};
+
+		let parsed = roundtrip(&message);
+		assert_eq!(parsed.r#type, MessageType::Signal);
+		assert!(parsed.body.is_none());
+	}
+
+	/// A header field claiming a STRING value with a length far larger than the actual buffer must be
+	/// rejected with `MalformedHeader`, not panic -- this is the shape a hostile or buggy bus peer could
+	/// send, since nothing on the wire guarantees a declared length matches the bytes that follow it.
+	#[test]
+	fn deserialize_rejects_oversized_declared_string_length() {
+		// field code 2 (INTERFACE), signature "s"
+		let mut header_fields = vec![2_u8, 1_u8, b's', 0_u8];
+		while !header_fields.len().is_multiple_of(4) {
+			header_fields.push(0);
+		}
+		header_fields.extend_from_slice(&0xffff_ffff_u32.to_le_bytes()); // declared string length
+		header_fields.extend_from_slice(b"short"); // far less than declared
+
+		let mut buf = vec![b'l', 1, 0, 1]; // endianness, MethodCall, flags, protocol version
+		buf.extend_from_slice(&0_u32.to_le_bytes()); // body length
+		buf.extend_from_slice(&1_u32.to_le_bytes()); // serial
+		buf.extend_from_slice(&(header_fields.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&header_fields);
+		while !buf.len().is_multiple_of(8) {
+			buf.push(0);
+		}
+
+		match Message::deserialize(&buf, &[]) {
+			Err(MessageParseError::MalformedHeader) => {},
+			other => panic!("expected MalformedHeader, got {:?}", other),
+		}
+	}
+
+	/// A header whose declared length runs past the end of the buffer (eg a truncated final field)
+	/// must read as an incomplete message rather than panic.
+	#[test]
+	fn deserialize_rejects_truncated_header_field() {
+		// field code 2 (INTERFACE), signature "s" -- deliberately left with no value bytes, and the
+		// length below lies about how many more bytes follow.
+		let header_fields = vec![2_u8, 1_u8, b's', 0_u8];
+
+		let mut buf = vec![b'l', 1, 0, 1]; // endianness, MethodCall, flags, protocol version
+		buf.extend_from_slice(&0_u32.to_le_bytes());
+		buf.extend_from_slice(&1_u32.to_le_bytes());
+		buf.extend_from_slice(&((header_fields.len() + 100) as u32).to_le_bytes()); // lies about the length
+		buf.extend_from_slice(&header_fields);
+		while !buf.len().is_multiple_of(8) {
+			buf.push(0);
+		}
+
+		// buf.len() < body_end (header_fields_len inflated by the lie), so this should read as
+		// "not a complete message yet", not a panic.
+		assert!(matches!(Message::deserialize(&buf, &[]), Ok(None)));
+	}
+}
@@ -0,0 +1,280 @@
+//! Hosting objects on a bus and responding to incoming method calls.
+
+use crate::{
+	client::{Client, ClientError},
+	message::{Message, MessageType, FLAG_NO_REPLY_EXPECTED},
+	types::{ObjectPath, Signature, Variant},
+};
+
+/// A registered method handler, invoked with the call's body and returning the reply body,
+/// or a D-Bus error name and optional error body.
+pub type MethodHandler = Box<dyn FnMut(Option<Variant>) -> Result<Option<Variant>, (String, Option<Variant>)>>;
+
+/// A registered read-only property getter.
+pub type PropertyGetter = Box<dyn FnMut() -> Variant>;
+
+/// A registered method handler plus the argument signatures it was declared with, so
+/// [`Server::introspect`] can describe them.
+struct MethodEntry {
+	in_signature: Vec<Signature>,
+	out_signature: Vec<Signature>,
+	handler: MethodHandler,
+}
+
+/// A registered property getter plus its declared value type, so [`Server::introspect`] can describe it.
+struct PropertyEntry {
+	signature: Signature,
+	getter: PropertyGetter,
+}
+
+/// A server that hosts objects on a bus and dispatches incoming method calls to registered handlers.
+///
+/// Unmatched calls get an automatic `org.freedesktop.DBus.Error.UnknownMethod` reply.
+/// `org.freedesktop.DBus.Introspectable.Introspect` and `org.freedesktop.DBus.Properties.Get`/`GetAll`
+/// are served from the registration metadata without needing to be registered explicitly.
+///
+/// `Server` has no notion of signals (emitting one is a plain [`Client::emit_signal`] call, independent
+/// of any object registered here), so introspection XML never describes `<signal>` elements.
+pub struct Server {
+	client: Client,
+	methods: std::collections::HashMap<(ObjectPath, String, String), MethodEntry>,
+	properties: std::collections::HashMap<(ObjectPath, String, String), PropertyEntry>,
+}
+
+/// The flags accepted by [`Server::request_name`], corresponding to the `org.freedesktop.DBus.RequestName` flags.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestNameFlags {
+	pub allow_replacement: bool,
+	pub replace_existing: bool,
+	pub do_not_queue: bool,
+}
+
+/// The outcome of a [`Server::request_name`] call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestNameReply {
+	PrimaryOwner,
+	InQueue,
+	Exists,
+	AlreadyOwner,
+}
+
+impl Server {
+	/// Wraps a [`Client`] in a `Server`, ready to register objects and serve incoming method calls.
+	pub fn new(client: Client) -> Self {
+		Server {
+			client,
+			methods: Default::default(),
+			properties: Default::default(),
+		}
+	}
+
+	/// Requests ownership of a bus name via `org.freedesktop.DBus.RequestName`.
+	pub fn request_name(&mut self, name: &str, flags: RequestNameFlags) -> Result<RequestNameReply, ClientError> {
+		let mut wire_flags = 0_u32;
+		if flags.allow_replacement {
+			wire_flags |= 0x1;
+		}
+		if flags.replace_existing {
+			wire_flags |= 0x2;
+		}
+		if flags.do_not_queue {
+			wire_flags |= 0x4;
+		}
+
+		let reply =
+			self.client.method_call(
+				"org.freedesktop.DBus".to_owned(),
+				ObjectPath("/org/freedesktop/DBus".to_owned()),
+				"org.freedesktop.DBus".to_owned(),
+				"RequestName".to_owned(),
+				Some(&Variant::Tuple { elements: vec![Variant::String(name.to_owned()), Variant::U32(wire_flags)] }),
+			)?
+			.ok_or(ClientError::UnexpectedResponse)?
+			.into_u32()
+			.map_err(|_| ClientError::UnexpectedResponse)?;
+
+		match reply {
+			1 => Ok(RequestNameReply::PrimaryOwner),
+			2 => Ok(RequestNameReply::InQueue),
+			3 => Ok(RequestNameReply::Exists),
+			4 => Ok(RequestNameReply::AlreadyOwner),
+			_ => Err(ClientError::UnexpectedResponse),
+		}
+	}
+
+	/// Registers a method call handler for `(path, interface, member)`, declaring its argument and
+	/// return value signatures for [`Server::introspect`].
+	pub fn add_method(
+		&mut self,
+		path: ObjectPath,
+		interface: String,
+		member: String,
+		in_signature: Vec<Signature>,
+		out_signature: Vec<Signature>,
+		handler: MethodHandler,
+	) {
+		self.methods.insert((path, interface, member), MethodEntry { in_signature, out_signature, handler });
+	}
+
+	/// Registers a read-only property, served by `org.freedesktop.DBus.Properties.Get`/`GetAll`.
+	pub fn add_property(&mut self, path: ObjectPath, interface: String, name: String, signature: Signature, getter: PropertyGetter) {
+		self.properties.insert((path, interface, name), PropertyEntry { signature, getter });
+	}
+
+	/// Blocks until an incoming method call is received, dispatches it, and sends the reply, unless the
+	/// call was flagged as expecting no reply.
+	pub fn serve_one(&mut self) -> Result<(), ClientError> {
+		let call = self.client.recv_method_call()?;
+
+		let path = call.path.clone().unwrap_or(ObjectPath(String::new()));
+		let interface = call.interface.clone().unwrap_or_default();
+		let member = call.member.clone().unwrap_or_default();
+
+		let result = self.dispatch(&path, &interface, &member, call.body.clone());
+
+		if call.flags & FLAG_NO_REPLY_EXPECTED != 0 {
+			return Ok(());
+		}
+
+		let reply = match result {
+			Ok(body) =>
+				Message {
+					r#type: MessageType::MethodReturn,
+					flags: 0,
+					serial: 0,
+					path: None,
+					interface: None,
+					member: None,
+					error_name: None,
+					reply_serial: Some(call.serial),
+					destination: call.sender,
+					sender: None,
+					body,
+				},
+
+			Err((error_name, body)) =>
+				Message {
+					r#type: MessageType::Error,
+					flags: 0,
+					serial: 0,
+					path: None,
+					interface: None,
+					member: None,
+					error_name: Some(error_name),
+					reply_serial: Some(call.serial),
+					destination: call.sender,
+					sender: None,
+					body,
+				},
+		};
+
+		self.client.send_serial(&reply)?;
+
+		Ok(())
+	}
+
+	fn dispatch(
+		&mut self,
+		path: &ObjectPath,
+		interface: &str,
+		member: &str,
+		body: Option<Variant>,
+	) -> Result<Option<Variant>, (String, Option<Variant>)> {
+		if let Some(entry) = self.methods.get_mut(&(path.clone(), interface.to_owned(), member.to_owned())) {
+			return (entry.handler)(body);
+		}
+
+		match (interface, member) {
+			("org.freedesktop.DBus.Introspectable", "Introspect") => Ok(Some(Variant::String(self.introspect(path)))),
+			("org.freedesktop.DBus.Properties", "Get") => self.get_property(path, body),
+			("org.freedesktop.DBus.Properties", "GetAll") => self.get_all_properties(path, body),
+			_ => Err(("org.freedesktop.DBus.Error.UnknownMethod".to_owned(), None)),
+		}
+	}
+
+	fn introspect(&self, path: &ObjectPath) -> String {
+		type Methods<'a> = Vec<(&'a str, &'a [Signature], &'a [Signature])>;
+		type Properties<'a> = Vec<(&'a str, &'a Signature)>;
+
+		let mut interfaces: std::collections::BTreeMap<&str, (Methods<'_>, Properties<'_>)> = Default::default();
+
+		for ((object_path, interface, member), entry) in &self.methods {
+			if object_path == path {
+				interfaces.entry(interface).or_default().0.push((member, &entry.in_signature, &entry.out_signature));
+			}
+		}
+		for ((object_path, interface, name), entry) in &self.properties {
+			if object_path == path {
+				interfaces.entry(interface).or_default().1.push((name, &entry.signature));
+			}
+		}
+
+		let mut xml = String::new();
+		xml.push_str("<!DOCTYPE node PUBLIC \"-//freedesktop//DTD D-BUS Object Introspection 1.0//EN\"\n");
+		xml.push_str("\"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd\">\n");
+		xml.push_str("<node>\n");
+		for (interface, (methods, properties)) in interfaces {
+			xml.push_str(&format!("  <interface name=\"{}\">\n", interface));
+			for (method, in_signature, out_signature) in methods {
+				if in_signature.is_empty() && out_signature.is_empty() {
+					xml.push_str(&format!("    <method name=\"{}\"/>\n", method));
+					continue;
+				}
+				xml.push_str(&format!("    <method name=\"{}\">\n", method));
+				for arg in in_signature {
+					xml.push_str(&format!("      <arg type=\"{}\" direction=\"in\"/>\n", arg));
+				}
+				for arg in out_signature {
+					xml.push_str(&format!("      <arg type=\"{}\" direction=\"out\"/>\n", arg));
+				}
+				xml.push_str("    </method>\n");
+			}
+			for (property, signature) in properties {
+				xml.push_str(&format!("    <property name=\"{}\" type=\"{}\" access=\"read\"/>\n", property, signature));
+			}
+			xml.push_str("  </interface>\n");
+		}
+		xml.push_str("</node>\n");
+		xml
+	}
+
+	fn get_property(&mut self, path: &ObjectPath, body: Option<Variant>) -> Result<Option<Variant>, (String, Option<Variant>)> {
+		let (interface, name) = read_two_strings(body)?;
+
+		let entry =
+			self.properties.get_mut(&(path.clone(), interface, name))
+			.ok_or_else(|| ("org.freedesktop.DBus.Error.UnknownProperty".to_owned(), None))?;
+
+		Ok(Some(Variant::Variant(Box::new((entry.getter)()))))
+	}
+
+	fn get_all_properties(&mut self, path: &ObjectPath, body: Option<Variant>) -> Result<Option<Variant>, (String, Option<Variant>)> {
+		let interface =
+			body
+			.ok_or_else(invalid_args)?
+			.into_string().map_err(|_| invalid_args())?;
+
+		let mut elements = vec![];
+		for ((object_path, object_interface, name), entry) in &mut self.properties {
+			if *object_path == *path && *object_interface == interface {
+				elements.push((Variant::String(name.clone()), Variant::Variant(Box::new((entry.getter)()))));
+			}
+		}
+
+		Ok(Some(Variant::Dict { key_signature: Signature::String, value_signature: Signature::Variant, elements }))
+	}
+}
+
+fn invalid_args() -> (String, Option<Variant>) {
+	("org.freedesktop.DBus.Error.InvalidArgs".to_owned(), None)
+}
+
+fn read_two_strings(body: Option<Variant>) -> Result<(String, String), (String, Option<Variant>)> {
+	let mut elements = body.ok_or_else(invalid_args)?.into_tuple().map_err(|_| invalid_args())?;
+	if elements.len() != 2 {
+		return Err(invalid_args());
+	}
+	let second = elements.remove(1).into_string().map_err(|_| invalid_args())?;
+	let first = elements.remove(0).into_string().map_err(|_| invalid_args())?;
+	Ok((first, second))
+}
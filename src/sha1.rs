@@ -0,0 +1,104 @@
+//! A minimal SHA-1 implementation, used only for the DBUS_COOKIE_SHA1 SASL mechanism.
+//!
+//! SHA-1 is cryptographically broken and must not be used for anything security-sensitive,
+//! but the D-Bus spec mandates it for this particular authentication mechanism.
+
+/// Computes the SHA-1 digest of the given bytes.
+pub(crate) fn digest(input: &[u8]) -> [u8; 20] {
+	let mut h0: u32 = 0x67452301;
+	let mut h1: u32 = 0xEFCDAB89;
+	let mut h2: u32 = 0x98BADCFE;
+	let mut h3: u32 = 0x10325476;
+	let mut h4: u32 = 0xC3D2E1F0;
+
+	let mut message = input.to_vec();
+	let message_bit_len = (input.len() as u64) * 8;
+
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend_from_slice(&message_bit_len.to_be_bytes());
+
+	for chunk in message.chunks_exact(64) {
+		let mut w = [0_u32; 80];
+
+		for (i, word) in chunk.chunks_exact(4).enumerate() {
+			w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+		}
+
+		for i in 16..80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+
+		let mut a = h0;
+		let mut b = h1;
+		let mut c = h2;
+		let mut d = h3;
+		let mut e = h4;
+
+		for (i, &w_i) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0..=19 => ((b & c) | ((!b) & d), 0x5A827999),
+				20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+				_ => (b ^ c ^ d, 0xCA62C1D6),
+			};
+
+			let temp =
+				a.rotate_left(5)
+				.wrapping_add(f)
+				.wrapping_add(e)
+				.wrapping_add(k)
+				.wrapping_add(w_i);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+
+		h0 = h0.wrapping_add(a);
+		h1 = h1.wrapping_add(b);
+		h2 = h2.wrapping_add(c);
+		h3 = h3.wrapping_add(d);
+		h4 = h4.wrapping_add(e);
+	}
+
+	let mut result = [0_u8; 20];
+	result[0..4].copy_from_slice(&h0.to_be_bytes());
+	result[4..8].copy_from_slice(&h1.to_be_bytes());
+	result[8..12].copy_from_slice(&h2.to_be_bytes());
+	result[12..16].copy_from_slice(&h3.to_be_bytes());
+	result[16..20].copy_from_slice(&h4.to_be_bytes());
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::digest;
+
+	fn hex(bytes: &[u8]) -> String {
+		bytes.iter().map(|b| format!("{:02x}", b)).collect()
+	}
+
+	// Test vectors from RFC 3174, section 7.3.
+	#[test]
+	fn rfc3174_one_block() {
+		assert_eq!(hex(&digest(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+	}
+
+	#[test]
+	fn rfc3174_multi_block() {
+		assert_eq!(
+			hex(&digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+			"84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+		);
+	}
+
+	#[test]
+	fn rfc3174_million_a() {
+		let input = vec![b'a'; 1_000_000];
+		assert_eq!(hex(&digest(&input)), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+	}
+}
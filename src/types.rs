@@ -0,0 +1,254 @@
+//! The D-Bus type system: signatures and the values (variants) that inhabit them.
+
+/// An object path, eg `/org/freedesktop/DBus`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectPath(pub String);
+
+/// A D-Bus type signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signature {
+	Byte,
+	Boolean,
+	I16,
+	U16,
+	I32,
+	U32,
+	I64,
+	U64,
+	Double,
+	String,
+	ObjectPath,
+	Signature,
+	Variant,
+	UnixFd,
+	Array(Box<Signature>),
+	Tuple(Vec<Signature>),
+	Dict(Box<Signature>, Box<Signature>),
+}
+
+impl Signature {
+	/// The alignment in bytes that a value of this type must be marshalled at.
+	pub(crate) fn alignment(&self) -> usize {
+		match self {
+			Signature::Byte | Signature::Signature | Signature::Variant => 1,
+			Signature::I16 | Signature::U16 => 2,
+			Signature::Boolean |
+			Signature::I32 | Signature::U32 |
+			Signature::String | Signature::ObjectPath |
+			Signature::UnixFd |
+			Signature::Array(_) | Signature::Dict(_, _) => 4,
+			Signature::I64 | Signature::U64 | Signature::Double | Signature::Tuple(_) => 8,
+		}
+	}
+
+	/// Parses a complete signature string, eg `"a{sv}"`, into one [`Signature`] per complete type it contains.
+	pub fn parse(s: &str) -> Result<Vec<Signature>, SignatureParseError> {
+		let mut chars = s.chars().peekable();
+		let mut result = vec![];
+
+		while chars.peek().is_some() {
+			result.push(Self::parse_one(&mut chars)?);
+		}
+
+		Ok(result)
+	}
+
+	fn parse_one(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<Signature, SignatureParseError> {
+		match chars.next().ok_or(SignatureParseError::UnexpectedEnd)? {
+			'y' => Ok(Signature::Byte),
+			'b' => Ok(Signature::Boolean),
+			'n' => Ok(Signature::I16),
+			'q' => Ok(Signature::U16),
+			'i' => Ok(Signature::I32),
+			'u' => Ok(Signature::U32),
+			'x' => Ok(Signature::I64),
+			't' => Ok(Signature::U64),
+			'd' => Ok(Signature::Double),
+			's' => Ok(Signature::String),
+			'o' => Ok(Signature::ObjectPath),
+			'g' => Ok(Signature::Signature),
+			'v' => Ok(Signature::Variant),
+			'h' => Ok(Signature::UnixFd),
+
+			'a' =>
+				if chars.peek() == Some(&'{') {
+					chars.next();
+					let key = Self::parse_one(chars)?;
+					let value = Self::parse_one(chars)?;
+					if chars.next() != Some('}') {
+						return Err(SignatureParseError::UnexpectedEnd);
+					}
+					Ok(Signature::Dict(Box::new(key), Box::new(value)))
+				}
+				else {
+					Ok(Signature::Array(Box::new(Self::parse_one(chars)?)))
+				},
+
+			'(' => {
+				let mut elements = vec![];
+				while chars.peek() != Some(&')') {
+					elements.push(Self::parse_one(chars)?);
+				}
+				chars.next();
+				Ok(Signature::Tuple(elements))
+			},
+
+			c => Err(SignatureParseError::UnknownTypeCode(c)),
+		}
+	}
+}
+
+impl std::fmt::Display for Signature {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Signature::Byte => f.write_str("y"),
+			Signature::Boolean => f.write_str("b"),
+			Signature::I16 => f.write_str("n"),
+			Signature::U16 => f.write_str("q"),
+			Signature::I32 => f.write_str("i"),
+			Signature::U32 => f.write_str("u"),
+			Signature::I64 => f.write_str("x"),
+			Signature::U64 => f.write_str("t"),
+			Signature::Double => f.write_str("d"),
+			Signature::String => f.write_str("s"),
+			Signature::ObjectPath => f.write_str("o"),
+			Signature::Signature => f.write_str("g"),
+			Signature::Variant => f.write_str("v"),
+			Signature::UnixFd => f.write_str("h"),
+			Signature::Array(element) => write!(f, "a{}", element),
+			Signature::Tuple(elements) => {
+				f.write_str("(")?;
+				for element in elements {
+					write!(f, "{}", element)?;
+				}
+				f.write_str(")")
+			},
+			Signature::Dict(key, value) => write!(f, "a{{{}{}}}", key, value),
+		}
+	}
+}
+
+/// An error parsing a [`Signature`] from its wire string representation.
+#[derive(Debug)]
+pub enum SignatureParseError {
+	UnexpectedEnd,
+	UnknownTypeCode(char),
+}
+
+impl std::fmt::Display for SignatureParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SignatureParseError::UnexpectedEnd => f.write_str("signature ended unexpectedly"),
+			SignatureParseError::UnknownTypeCode(c) => write!(f, "unknown type code {:?}", c),
+		}
+	}
+}
+
+impl std::error::Error for SignatureParseError {}
+
+/// A D-Bus value.
+#[derive(Clone, Debug)]
+pub enum Variant {
+	Byte(u8),
+	Boolean(bool),
+	I16(i16),
+	U16(u16),
+	I32(i32),
+	U32(u32),
+	I64(i64),
+	U64(u64),
+	Double(f64),
+	String(String),
+	ObjectPath(ObjectPath),
+	/// The complete types making up a signature string, eg the body signature `"su"` is two elements.
+	Signature(Vec<Signature>),
+
+	Variant(Box<Variant>),
+
+	/// A file descriptor passed out-of-band alongside the message that carried this value.
+	///
+	/// On a received message, the descriptor is newly allocated in this process and owned by the caller,
+	/// who is responsible for closing it. A descriptor passed to [`crate::client::Client::method_call`]
+	/// or [`crate::client::Client::emit_signal`] remains owned by the caller; this crate does not close it.
+	UnixFd(std::os::unix::io::RawFd),
+
+	Array { element_signature: Signature, elements: Vec<Variant> },
+	Tuple { elements: Vec<Variant> },
+	Dict { key_signature: Signature, value_signature: Signature, elements: Vec<(Variant, Variant)> },
+}
+
+macro_rules! into_fn {
+	($name:ident, $variant:ident, $ty:ty) => {
+		/// Returns the wrapped value, or `self` if this is not a
+		#[doc = concat!(" `Variant::", stringify!($variant), "`.")]
+		pub fn $name(self) -> Result<$ty, Variant> {
+			match self {
+				Variant::$variant(value) => Ok(value),
+				other => Err(other),
+			}
+		}
+	};
+}
+
+impl Variant {
+	pub fn signature(&self) -> Signature {
+		match self {
+			Variant::Byte(_) => Signature::Byte,
+			Variant::Boolean(_) => Signature::Boolean,
+			Variant::I16(_) => Signature::I16,
+			Variant::U16(_) => Signature::U16,
+			Variant::I32(_) => Signature::I32,
+			Variant::U32(_) => Signature::U32,
+			Variant::I64(_) => Signature::I64,
+			Variant::U64(_) => Signature::U64,
+			Variant::Double(_) => Signature::Double,
+			Variant::String(_) => Signature::String,
+			Variant::ObjectPath(_) => Signature::ObjectPath,
+			Variant::Signature(_) => Signature::Signature,
+			Variant::Variant(_) => Signature::Variant,
+			Variant::UnixFd(_) => Signature::UnixFd,
+			Variant::Array { element_signature, .. } => Signature::Array(Box::new(element_signature.clone())),
+			Variant::Tuple { elements } => Signature::Tuple(elements.iter().map(Variant::signature).collect()),
+			Variant::Dict { key_signature, value_signature, .. } =>
+				Signature::Dict(Box::new(key_signature.clone()), Box::new(value_signature.clone())),
+		}
+	}
+
+	into_fn!(into_byte, Byte, u8);
+	into_fn!(into_boolean, Boolean, bool);
+	into_fn!(into_i16, I16, i16);
+	into_fn!(into_u16, U16, u16);
+	into_fn!(into_i32, I32, i32);
+	into_fn!(into_u32, U32, u32);
+	into_fn!(into_i64, I64, i64);
+	into_fn!(into_u64, U64, u64);
+	into_fn!(into_double, Double, f64);
+	into_fn!(into_string, String, String);
+	into_fn!(into_object_path, ObjectPath, ObjectPath);
+	into_fn!(into_signature, Signature, Vec<Signature>);
+	into_fn!(into_unix_fd, UnixFd, std::os::unix::io::RawFd);
+
+	/// Returns the inner value, or `self` if this is not a `Variant::Variant`.
+	pub fn into_variant(self) -> Result<Variant, Variant> {
+		match self {
+			Variant::Variant(inner) => Ok(*inner),
+			other => Err(other),
+		}
+	}
+
+	/// Returns the array elements, or `self` if this is not a `Variant::Array` with the given element signature.
+	pub fn into_array(self, element_signature: &Signature) -> Result<Vec<Variant>, Variant> {
+		match self {
+			Variant::Array { element_signature: actual, elements } if &actual == element_signature => Ok(elements),
+			other => Err(other),
+		}
+	}
+
+	/// Returns the tuple elements, or `self` if this is not a `Variant::Tuple`.
+	pub fn into_tuple(self) -> Result<Vec<Variant>, Variant> {
+		match self {
+			Variant::Tuple { elements } => Ok(elements),
+			other => Err(other),
+		}
+	}
+}